@@ -0,0 +1,241 @@
+use crate::structure::{GraphNodeUid, OpenPgpKey, OpenPgpSig};
+use petgraph::algo::{page_rank, tarjan_scc};
+use petgraph::graphmap::DiGraphMap;
+use petgraph::visit::NodeIndexable;
+use petgraph::Direction;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+const PAGE_RANK_DAMPING_FACTOR: f64 = 0.85;
+const PAGE_RANK_ITERATIONS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct KeyReport {
+    pub(crate) fingerprint: String,
+    pub(crate) uid: String,
+    pub(crate) in_strong_set: bool,
+    pub(crate) in_degree: usize,
+    pub(crate) out_degree: usize,
+    pub(crate) betweenness: f64,
+    pub(crate) page_rank: f64,
+    pub(crate) mean_shortest_distance: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct AnalyzeReport {
+    pub(crate) keys: usize,
+    pub(crate) certifications: usize,
+    pub(crate) components: Vec<usize>,
+    pub(crate) strong_set: Vec<String>,
+    pub(crate) strong_set_mean_shortest_distance: Option<f64>,
+    pub(crate) ranking: Vec<KeyReport>,
+}
+
+/// Collapse the graph of UIDs into a graph of keys, dropping certifications between UIDs of the same key
+pub(crate) fn key_graph<'a>(
+    graph: &DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>,
+) -> DiGraphMap<&'a str, ()> {
+    let mut key_graph: DiGraphMap<&str, ()> = DiGraphMap::new();
+    graph.nodes().for_each(|node| {
+        key_graph.add_node(node.fingerprint);
+    });
+    graph.all_edges().for_each(|(issuer, target, _)| {
+        if issuer.fingerprint != target.fingerprint {
+            key_graph.add_edge(issuer.fingerprint, target.fingerprint, ());
+        }
+    });
+    key_graph
+}
+
+/// Breadth-first distances from `source`, following edges in `direction`
+pub(crate) fn distances<'a>(
+    graph: &DiGraphMap<&'a str, ()>,
+    source: &'a str,
+    direction: Direction,
+) -> HashMap<&'a str, usize> {
+    let mut distances: HashMap<&str, usize> = HashMap::from([(source, 0)]);
+    let mut queue: VecDeque<&str> = VecDeque::from([source]);
+    while let Some(node) = queue.pop_front() {
+        let distance = distances[node] + 1;
+        graph
+            .neighbors_directed(node, direction)
+            .for_each(|neighbour| {
+                if !distances.contains_key(neighbour) {
+                    distances.insert(neighbour, distance);
+                    queue.push_back(neighbour);
+                }
+            });
+    }
+    distances
+}
+
+/// The largest strongly connected component, the "strong set" of the web of trust
+pub(crate) fn strong_set<'a>(graph: &DiGraphMap<&'a str, ()>) -> (Vec<usize>, HashSet<&'a str>) {
+    let mut components = tarjan_scc(graph);
+    components.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    let sizes = components.iter().map(|v| v.len()).collect();
+    let strong_set = components
+        .into_iter()
+        .next()
+        .filter(|v| v.len() > 1)
+        .map(|v| v.into_iter().collect())
+        .unwrap_or_default();
+    (sizes, strong_set)
+}
+
+/// Brandes' algorithm of betweenness centrality on an unweighted directed graph
+fn betweenness<'a>(graph: &DiGraphMap<&'a str, ()>) -> HashMap<&'a str, f64> {
+    let mut centrality: HashMap<&str, f64> = graph.nodes().map(|v| (v, 0.0)).collect();
+    graph.nodes().for_each(|source| {
+        let mut stack: Vec<&str> = Default::default();
+        let mut predecessors: HashMap<&str, Vec<&str>> = Default::default();
+        let mut paths: HashMap<&str, f64> = HashMap::from([(source, 1.0)]);
+        let mut distances: HashMap<&str, usize> = HashMap::from([(source, 0)]);
+        let mut queue: VecDeque<&str> = VecDeque::from([source]);
+
+        while let Some(node) = queue.pop_front() {
+            stack.push(node);
+            let distance = distances[node];
+            graph.neighbors(node).for_each(|neighbour| {
+                if !distances.contains_key(neighbour) {
+                    distances.insert(neighbour, distance + 1);
+                    queue.push_back(neighbour);
+                }
+                if distances[neighbour] == distance + 1 {
+                    *paths.entry(neighbour).or_default() += paths[node];
+                    predecessors.entry(neighbour).or_default().push(node);
+                }
+            });
+        }
+
+        let mut dependency: HashMap<&str, f64> = Default::default();
+        while let Some(node) = stack.pop() {
+            let node_dependency = dependency.get(node).copied().unwrap_or(0.0);
+            predecessors
+                .get(node)
+                .into_iter()
+                .flatten()
+                .for_each(|predecessor| {
+                    *dependency.entry(predecessor).or_default() +=
+                        paths[predecessor] / paths[node] * (1.0 + node_dependency);
+                });
+            if node != source {
+                *centrality.entry(node).or_default() += node_dependency;
+            }
+        }
+    });
+    centrality
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f64)
+    }
+}
+
+pub(crate) fn analyze(
+    graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>,
+    key_set: &HashMap<Arc<String>, OpenPgpKey>,
+) -> AnalyzeReport {
+    let key_graph = key_graph(graph);
+    let (components, strong_set) = strong_set(&key_graph);
+    let betweenness = betweenness(&key_graph);
+    let page_rank = page_rank(&key_graph, PAGE_RANK_DAMPING_FACTOR, PAGE_RANK_ITERATIONS);
+
+    let mut ranking: Vec<KeyReport> = key_graph
+        .nodes()
+        .map(|node| {
+            // The mean shortest distance is measured from every key in the strong set to this key
+            let mean_shortest_distance = mean(
+                distances(&key_graph, node, Direction::Incoming)
+                    .into_iter()
+                    .filter(|(v, _)| *v != node && strong_set.contains(v))
+                    .map(|(_, distance)| distance as f64),
+            );
+            KeyReport {
+                fingerprint: node.to_string(),
+                uid: key_set
+                    .get(&node.to_string())
+                    .map(|v| v.primary_user_id.to_string())
+                    .unwrap_or_default(),
+                in_strong_set: strong_set.contains(node),
+                in_degree: key_graph
+                    .neighbors_directed(node, Direction::Incoming)
+                    .count(),
+                out_degree: key_graph
+                    .neighbors_directed(node, Direction::Outgoing)
+                    .count(),
+                betweenness: betweenness.get(node).copied().unwrap_or(0.0),
+                page_rank: page_rank[key_graph.to_index(node)],
+                mean_shortest_distance,
+            }
+        })
+        .collect();
+
+    ranking.sort_by(|a, b| {
+        b.page_rank
+            .partial_cmp(&a.page_rank)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.fingerprint.cmp(&b.fingerprint))
+    });
+
+    let mut strong_set: Vec<String> = strong_set.into_iter().map(|v| v.to_string()).collect();
+    strong_set.sort();
+
+    AnalyzeReport {
+        keys: key_graph.node_count(),
+        certifications: key_graph.edge_count(),
+        components,
+        strong_set_mean_shortest_distance: mean(
+            ranking
+                .iter()
+                .filter(|v| v.in_strong_set)
+                .filter_map(|v| v.mean_shortest_distance),
+        ),
+        strong_set,
+        ranking,
+    }
+}
+
+impl Display for AnalyzeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Keys:\t\t\t{}", self.keys)?;
+        writeln!(f, "Certifications:\t\t{}", self.certifications)?;
+        writeln!(f, "Components:\t\t{}", self.components.len())?;
+        writeln!(f, "Strong set size:\t{}", self.strong_set.len())?;
+        writeln!(
+            f,
+            "Strong set MSD:\t\t{}",
+            self.strong_set_mean_shortest_distance
+                .map_or_else(|| "-".to_string(), |v| format!("{:.4}", v))
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>4}  {:<40}  {:>6}  {:>3}  {:>3}  {:>11}  {:>9}  {:>7}  UID",
+            "Rank", "Fingerprint", "Strong", "In", "Out", "Betweenness", "PageRank", "MSD"
+        )?;
+        self.ranking.iter().enumerate().try_for_each(|(i, v)| {
+            writeln!(
+                f,
+                "{:>4}  {:<40}  {:>6}  {:>3}  {:>3}  {:>11.2}  {:>9.6}  {:>7}  {}",
+                i + 1,
+                v.fingerprint,
+                if v.in_strong_set { "yes" } else { "no" },
+                v.in_degree,
+                v.out_degree,
+                v.betweenness,
+                v.page_rank,
+                v.mean_shortest_distance
+                    .map_or_else(|| "-".to_string(), |v| format!("{:.4}", v)),
+                v.uid
+            )
+        })
+    }
+}
@@ -0,0 +1,260 @@
+use sequoia_openpgp::cert::ValidCert;
+use sequoia_openpgp::types::{HashAlgorithm, PublicKeyAlgorithm};
+use sequoia_openpgp::KeyHandle;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const RSA_MIN_BITS: usize = 2048;
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "issue", rename_all = "kebab-case")]
+pub(crate) enum AuditIssue {
+    WeakAlgorithm {
+        fingerprint: String,
+        algorithm: String,
+        bits: Option<usize>,
+    },
+    Sha1Certification {
+        issuer: String,
+        uid: String,
+    },
+    NoEncryptionSubkey,
+    NearExpiry {
+        expiration_time: u64,
+    },
+    OnlySelfSigned,
+    RejectedByPolicy {
+        reason: String,
+    },
+}
+
+impl AuditIssue {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            AuditIssue::WeakAlgorithm { .. } => "weak-algorithm",
+            AuditIssue::Sha1Certification { .. } => "sha1-certification",
+            AuditIssue::NoEncryptionSubkey => "no-encryption-subkey",
+            AuditIssue::NearExpiry { .. } => "near-expiry",
+            AuditIssue::OnlySelfSigned => "only-self-signed",
+            AuditIssue::RejectedByPolicy { .. } => "rejected-by-policy",
+        }
+    }
+}
+
+impl Display for AuditIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditIssue::WeakAlgorithm {
+                fingerprint,
+                algorithm,
+                bits,
+            } => match bits {
+                Some(bits) => write!(
+                    f,
+                    "weak algorithm {} {} bits on {}",
+                    algorithm, bits, fingerprint
+                ),
+                None => write!(f, "weak algorithm {} on {}", algorithm, fingerprint),
+            },
+            AuditIssue::Sha1Certification { issuer, uid } => {
+                write!(f, "SHA-1 certification by {} on {}", issuer, uid)
+            }
+            AuditIssue::NoEncryptionSubkey => write!(f, "no valid encryption subkey"),
+            AuditIssue::NearExpiry { expiration_time } => write!(
+                f,
+                "expires on {}",
+                crate::matrix::format_date(*expiration_time)
+            ),
+            AuditIssue::OnlySelfSigned => write!(f, "only self-signed"),
+            AuditIssue::RejectedByPolicy { reason } => write!(f, "rejected by policy: {}", reason),
+        }
+    }
+}
+
+fn is_weak_algorithm(algorithm: PublicKeyAlgorithm, bits: Option<usize>) -> bool {
+    #[allow(deprecated)]
+    match algorithm {
+        PublicKeyAlgorithm::RSAEncryptSign
+        | PublicKeyAlgorithm::RSAEncrypt
+        | PublicKeyAlgorithm::RSASign => bits.is_some_and(|v| v < RSA_MIN_BITS),
+        PublicKeyAlgorithm::DSA
+        | PublicKeyAlgorithm::ElGamalEncrypt
+        | PublicKeyAlgorithm::ElGamalEncryptSign => true,
+        _ => false,
+    }
+}
+
+/// Look for weak or problematic properties of a cert valid under the policy
+pub(crate) fn audit_cert(cert: &ValidCert, warn_expiry: Duration) -> Vec<AuditIssue> {
+    let mut issues: Vec<AuditIssue> = Default::default();
+    let fingerprint = cert.fingerprint();
+
+    cert.keys().revoked(false).for_each(|key| {
+        let bits = key.mpis().bits();
+        if is_weak_algorithm(key.pk_algo(), bits) {
+            issues.push(AuditIssue::WeakAlgorithm {
+                fingerprint: key.fingerprint().to_string(),
+                algorithm: key.pk_algo().to_string(),
+                bits,
+            });
+        }
+    });
+
+    // Certifications of the valid UIDs are filtered by the policy, which rejects SHA-1 ones, so look at all of them
+    cert.userids().for_each(|uid| {
+        uid.bundle()
+            .certifications2()
+            .filter(|sig| sig.hash_algo() == HashAlgorithm::SHA1)
+            .for_each(|sig| {
+                issues.push(AuditIssue::Sha1Certification {
+                    // The issuer fingerprint if any, or else the issuer key ID
+                    issuer: sig
+                        .get_issuers()
+                        .first()
+                        .map(|v| v.to_hex())
+                        .unwrap_or_default(),
+                    uid: uid.userid().to_string(),
+                });
+            })
+    });
+
+    if cert
+        .keys()
+        .alive()
+        .revoked(false)
+        .for_transport_encryption()
+        .for_storage_encryption()
+        .next()
+        .is_none()
+    {
+        issues.push(AuditIssue::NoEncryptionSubkey);
+    }
+
+    if let Some(expiration_time) = cert.primary_key().key_expiration_time() {
+        let now = SystemTime::now();
+        if expiration_time > now && expiration_time < now + warn_expiry {
+            issues.push(AuditIssue::NearExpiry {
+                expiration_time: expiration_time
+                    .duration_since(UNIX_EPOCH)
+                    .map(|v| v.as_secs())
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    // Like the SHA-1 check, look at all certifications, a certification may name its issuer by the key ID only
+    let primary = KeyHandle::from(&fingerprint);
+    let has_third_party_certification = cert.userids().any(|uid| {
+        uid.bundle().certifications2().any(|sig| {
+            sig.get_issuers()
+                .iter()
+                .any(|issuer| !issuer.aliases(&primary))
+        })
+    });
+    if !has_third_party_certification {
+        issues.push(AuditIssue::OnlySelfSigned);
+    }
+
+    issues
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct AuditEntry {
+    pub(crate) fingerprint: String,
+    pub(crate) uid: String,
+    pub(crate) issues: Vec<AuditIssue>,
+}
+
+/// Summarize the audit of every key with at least one issue, ordered by fingerprint
+pub(crate) fn audit_summary(audit: &HashMap<Arc<String>, AuditEntry>) -> Vec<&AuditEntry> {
+    let mut summary: Vec<&AuditEntry> = audit.values().filter(|v| !v.issues.is_empty()).collect();
+    summary.sort_by(|a, b| a.fingerprint.cmp(&b.fingerprint));
+    summary
+}
+
+impl Display for AuditEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}\t{}", self.fingerprint, self.uid)?;
+        self.issues.iter().try_for_each(|v| writeln!(f, "\t{}", v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{certify_with, generate, POLICY};
+    use sequoia_openpgp::packet::signature::SignatureBuilder;
+    use sequoia_openpgp::types::SignatureType;
+    use sequoia_openpgp::Cert;
+
+    /// `cert` with a certification by `issuer` naming it by the key ID only
+    fn certify_by_key_id(cert: Cert, issuer: &Cert, hash_algo: HashAlgorithm) -> Cert {
+        let builder = SignatureBuilder::new(SignatureType::GenericCertification)
+            .set_hash_algo(hash_algo)
+            .set_issuer(issuer.keyid())
+            .unwrap();
+        let cert = certify_with(cert, issuer, builder);
+        assert!(cert
+            .userids()
+            .flat_map(|v| v.certifications())
+            .all(|v| v.issuer_fingerprints().next().is_none()));
+        cert
+    }
+
+    fn issues(cert: &Cert) -> Vec<AuditIssue> {
+        audit_cert(
+            &cert.with_policy(POLICY, None).unwrap(),
+            Duration::from_secs(0),
+        )
+    }
+
+    #[test]
+    fn only_self_signed() {
+        assert!(issues(&generate("alice")).contains(&AuditIssue::OnlySelfSigned));
+    }
+
+    #[test]
+    fn certification_by_key_id_is_third_party() {
+        let bob = generate("bob");
+        let alice = certify_by_key_id(generate("alice"), &bob, HashAlgorithm::SHA512);
+        let issues = issues(&alice);
+        assert!(!issues.contains(&AuditIssue::OnlySelfSigned));
+        assert!(!issues
+            .iter()
+            .any(|v| matches!(v, AuditIssue::Sha1Certification { .. })));
+    }
+
+    #[test]
+    fn sha1_certification_by_key_id() {
+        let bob = generate("bob");
+        let alice = certify_by_key_id(generate("alice"), &bob, HashAlgorithm::SHA1);
+        assert!(issues(&alice).contains(&AuditIssue::Sha1Certification {
+            issuer: bob.keyid().to_hex(),
+            uid: "alice".to_string(),
+        }));
+    }
+
+    #[test]
+    fn sha1_certification_is_third_party() {
+        let bob = generate("bob");
+        let alice = certify_by_key_id(generate("alice"), &bob, HashAlgorithm::SHA1);
+        assert!(!issues(&alice).contains(&AuditIssue::OnlySelfSigned));
+    }
+
+    #[test]
+    fn weak_algorithms() {
+        assert!(is_weak_algorithm(
+            PublicKeyAlgorithm::RSAEncryptSign,
+            Some(1024)
+        ));
+        assert!(!is_weak_algorithm(
+            PublicKeyAlgorithm::RSAEncryptSign,
+            Some(4096)
+        ));
+        assert!(is_weak_algorithm(PublicKeyAlgorithm::DSA, Some(3072)));
+        assert!(!is_weak_algorithm(PublicKeyAlgorithm::EdDSA, Some(256)));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::structure::{GraphNodeUid, OpenPgpKey, OpenPgpSig, SigType};
+use crate::ORGANIZATIONS;
+use petgraph::graphmap::DiGraphMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Trust-signed UIDs within one domain for a key to be taken as the CA of the domain without an `openpgp-ca@` UID
+const MIN_CA_MEMBERS: usize = 3;
+
+/// An organization using OpenPGP CA: its CA key and the keys of the domain certified by the CA
+#[derive(Debug, Clone)]
+pub(crate) struct Organization {
+    pub(crate) domain: String,
+    pub(crate) ca: String,
+    pub(crate) members: BTreeSet<String>,
+}
+
+fn email_domain(email: &str) -> Option<String> {
+    email
+        .rsplit_once('@')
+        .map(|(_, v)| v.to_ascii_lowercase())
+        .filter(|v| !v.is_empty())
+}
+
+/// Detect the CA keys: keys with an `openpgp-ca@` UID, or keys trust-signing many UIDs within one domain,
+/// and group the keys of the domain certified by them as their members
+pub(crate) fn detect_organizations(
+    key_set: &HashMap<Arc<String>, OpenPgpKey>,
+) -> Vec<Organization> {
+    // UIDs trust-signed by each issuer, by domain
+    let mut trust_signed: HashMap<&str, BTreeMap<String, BTreeSet<&str>>> = Default::default();
+    key_set.values().for_each(|pgp_key| {
+        pgp_key.user_ids.values().for_each(|pgp_uid| {
+            let Some(domain) = email_domain(&pgp_uid.email) else {
+                return;
+            };
+            pgp_uid
+                .sig_vec
+                .iter()
+                .filter(|sig| sig.trust_level > 0 && *sig.fingerprint != *pgp_key.id)
+                .for_each(|sig| {
+                    trust_signed
+                        .entry(sig.fingerprint.as_str())
+                        .or_default()
+                        .entry(domain.clone())
+                        .or_default()
+                        .insert(pgp_uid.uid.as_str());
+                });
+        });
+    });
+
+    let mut fingerprints: Vec<&Arc<String>> = key_set.keys().collect();
+    fingerprints.sort();
+    fingerprints
+        .into_iter()
+        .filter_map(|fingerprint| {
+            let pgp_key = &key_set[fingerprint];
+            let mut ca_uids: Vec<&str> = pgp_key
+                .user_ids
+                .values()
+                .filter(|v| !v.is_revoked)
+                .filter(|v| {
+                    v.email
+                        .rsplit_once('@')
+                        .is_some_and(|(local, _)| local.eq_ignore_ascii_case("openpgp-ca"))
+                })
+                .map(|v| v.email.as_str())
+                .collect();
+            ca_uids.sort();
+            let domain = ca_uids.first().and_then(|v| email_domain(v)).or_else(|| {
+                trust_signed
+                    .get(fingerprint.as_str())?
+                    .iter()
+                    .filter(|(_, uids)| uids.len() >= MIN_CA_MEMBERS)
+                    // The first domain with the most UIDs
+                    .rev()
+                    .max_by_key(|(_, uids)| uids.len())
+                    .map(|(domain, _)| domain.clone())
+            })?;
+            Some((fingerprint, domain))
+        })
+        .map(|(ca, domain)| {
+            let members = key_set
+                .values()
+                .filter(|pgp_key| pgp_key.id != *ca)
+                .filter(|pgp_key| {
+                    // A UID is certified if the newest signature of the CA on it isn't a revocation
+                    pgp_key.user_ids.values().any(|pgp_uid| {
+                        email_domain(&pgp_uid.email).as_ref() == Some(&domain)
+                            && pgp_uid
+                                .sig_vec
+                                .iter()
+                                .filter(|sig| sig.is_issued_by(ca))
+                                .max_by_key(|sig| sig.creation_time)
+                                .is_some_and(|sig| sig.sig_type != SigType::Revoke)
+                    })
+                })
+                .map(|pgp_key| pgp_key.id.to_string())
+                .collect();
+            Organization {
+                domain,
+                ca: ca.to_string(),
+                members,
+            }
+        })
+        .collect()
+}
+
+/// The organization of a CA key
+pub(crate) fn organization_of_ca(fingerprint: &str) -> Option<&'static Organization> {
+    ORGANIZATIONS.get()?.iter().find(|v| v.ca == fingerprint)
+}
+
+/// Whether a certification is a bridge: a trust signature of a CA on another CA
+pub(crate) fn is_bridge(issuer: &GraphNodeUid, target: &GraphNodeUid, sig: &OpenPgpSig) -> bool {
+    sig.trust_level > 0
+        && issuer.fingerprint != target.fingerprint
+        && organization_of_ca(issuer.fingerprint).is_some()
+        && organization_of_ca(target.fingerprint).is_some()
+}
+
+/// `cluster` subgraphs of the organizations, each with the UIDs of its CA and members
+/// A key in several organizations is drawn in the first one only
+pub(crate) fn organization_clusters(graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>) -> String {
+    let Some(organizations) = ORGANIZATIONS.get() else {
+        return Default::default();
+    };
+    let mut clustered: HashSet<usize> = Default::default();
+    organizations
+        .iter()
+        .enumerate()
+        .filter_map(|(i, organization)| {
+            let nodes: Vec<String> = graph
+                .nodes()
+                .enumerate()
+                .filter(|(_, uid)| {
+                    organization.ca == uid.fingerprint
+                        || organization.members.contains(uid.fingerprint)
+                })
+                .filter(|(j, _)| clustered.insert(*j))
+                .map(|(j, _)| j.to_string())
+                .collect();
+            (!nodes.is_empty()).then(|| {
+                format!(
+                    "    subgraph cluster_{} {{\n        label = \"{}\";\n        {};\n    }}\n",
+                    i,
+                    organization.domain.replace('"', "\\\""),
+                    nodes.join("; ")
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{certify, certify_with, generate, key_set};
+    use sequoia_openpgp::packet::signature::SignatureBuilder;
+    use sequoia_openpgp::types::SignatureType;
+    use sequoia_openpgp::Cert;
+    use std::time::{Duration, SystemTime};
+
+    /// `cert` with a trust signature of its UID by `issuer`
+    fn trust_sign(cert: Cert, issuer: &Cert) -> Cert {
+        let builder = SignatureBuilder::new(SignatureType::GenericCertification)
+            .set_trust_signature(1, 120)
+            .unwrap();
+        certify_with(cert, issuer, builder)
+    }
+
+    #[test]
+    fn email_domains() {
+        assert_eq!(
+            email_domain("alice@Example.ORG"),
+            Some("example.org".to_string())
+        );
+        assert_eq!(email_domain("alice@"), None);
+        assert_eq!(email_domain("alice"), None);
+    }
+
+    #[test]
+    fn ca_by_uid() {
+        let ca = generate("Example CA <openpgp-ca@example.org>");
+        let alice = certify(generate("alice <alice@example.org>"), &ca);
+        let mallory = certify(generate("mallory <mallory@other.org>"), &ca);
+        let bob = generate("bob <bob@example.org>");
+
+        let organizations = detect_organizations(&key_set(&[&ca, &alice, &mallory, &bob]));
+        assert_eq!(organizations.len(), 1);
+        assert_eq!(organizations[0].domain, "example.org");
+        assert_eq!(organizations[0].ca, ca.fingerprint().to_string());
+        assert_eq!(
+            organizations[0].members,
+            BTreeSet::from([alice.fingerprint().to_string()])
+        );
+    }
+
+    #[test]
+    fn revoked_certification_is_no_member() {
+        let ca = generate("Example CA <openpgp-ca@example.org>");
+        let alice = certify(generate("alice <alice@example.org>"), &ca);
+        let bob = certify(generate("bob <bob@example.org>"), &ca);
+        // Signatures are timed by the second, so the revocation has to be later than the certification
+        let revocation = SignatureBuilder::new(SignatureType::CertificationRevocation)
+            .set_signature_creation_time(SystemTime::now() + Duration::from_secs(1))
+            .unwrap();
+        let bob = certify_with(bob, &ca, revocation);
+
+        let organizations = detect_organizations(&key_set(&[&ca, &alice, &bob]));
+        assert_eq!(
+            organizations[0].members,
+            BTreeSet::from([alice.fingerprint().to_string()])
+        );
+    }
+
+    #[test]
+    fn ca_by_trust_signatures() {
+        let ca = generate("Example Certificates <certs@example.org>");
+        let members: Vec<Cert> = ["alice", "bob", "carol"]
+            .iter()
+            .map(|v| trust_sign(generate(&format!("{} <{}@example.org>", v, v)), &ca))
+            .collect();
+        let mut certs: Vec<&Cert> = members.iter().collect();
+        certs.push(&ca);
+
+        let organizations = detect_organizations(&key_set(&certs));
+        assert_eq!(organizations.len(), 1);
+        assert_eq!(organizations[0].domain, "example.org");
+        assert_eq!(organizations[0].members.len(), MIN_CA_MEMBERS);
+
+        // Too few trust signatures for a CA
+        let organizations = detect_organizations(&key_set(&certs[1..]));
+        assert!(organizations.is_empty());
+    }
+}
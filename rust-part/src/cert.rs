@@ -1,25 +1,52 @@
-use crate::structure::{GraphNodeUid, OpenPgpUid, OpenPgpUidLayer};
+use crate::cli::PolicyKind;
+use crate::structure::{
+    GraphNodeUid, OpenPgpKey, OpenPgpKeyCapability, OpenPgpKeyMaterial, OpenPgpPhoto, OpenPgpSig,
+    OpenPgpUid, OpenPgpUidLayer,
+};
 use crate::{CLI_ARGS, KEY_SET_MAP};
 use anyhow::{anyhow, Context};
-use log::{info, trace, warn};
+use clio::ClioPath;
+use log::{info, warn};
 use sequoia_net::KeyServer;
-use sequoia_openpgp::{Cert, Fingerprint};
+use sequoia_openpgp::cert::amalgamation::key::ValidErasedKeyAmalgamation;
+use sequoia_openpgp::cert::amalgamation::ValidAmalgamation;
+use sequoia_openpgp::cert::ValidCert;
+use sequoia_openpgp::packet::key::PublicParts;
+use sequoia_openpgp::packet::user_attribute::{Image, Subpacket};
+use sequoia_openpgp::packet::Signature;
+use sequoia_openpgp::policy::{
+    AsymmetricAlgorithm, HashAlgoSecurity, NullPolicy, Policy, StandardPolicy,
+};
+use sequoia_openpgp::types::HashAlgorithm;
+use sequoia_openpgp::{Cert, Fingerprint, KeyID};
+use sequoia_wot::{CertSynopsis, RevocationStatus, UserIDSynopsis};
 use serde::Serialize;
-use std::collections::{HashMap, HashSet};
-use std::default::Default;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Formatter;
-use std::sync::OnceLock;
+use std::fs::create_dir_all;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const NULL_POLICY: &NullPolicy = &NullPolicy::new();
 
 pub(crate) fn get_pgp_uid_by_node_uid<'a>(uid: &'a GraphNodeUid) -> Option<&'a OpenPgpUid> {
     KEY_SET_MAP
         .get()
-        .and_then(|v| {
-            v.get(&uid.fingerprint.to_string())
-                .map(|v| v.user_ids.get(&<&str as Into<String>>::into(uid.uid)))
-        })
+        .and_then(|v| v.get(&uid.fingerprint.to_string()).map(|v| v.uid(uid.uid)))
         .flatten()
 }
 
+/// Fingerprints of the issuers of certifications which are not in the key set, sorted
+pub(crate) fn unknown_issuers(key_set: &HashMap<Arc<String>, OpenPgpKey>) -> BTreeSet<&str> {
+    key_set
+        .values()
+        .flat_map(|pgp_key| pgp_key.user_ids.values())
+        .flat_map(|pgp_uid| pgp_uid.sig_vec.iter())
+        .map(|sig| sig.fingerprint.as_str())
+        .filter(|v| !v.is_empty() && !key_set.contains_key(&v.to_string()))
+        .collect()
+}
+
 pub(crate) fn simple_output<T>(object: &T, f: &mut Formatter<'_>, or: &String) -> std::fmt::Result
 where
     T: Serialize,
@@ -81,58 +108,347 @@ pub(crate) fn fetch_cert_from_keyserver_once_lock(
     }
 }
 
-pub(crate) fn fetch_cert_from_keyserver_recursive(
-    keyserver: &KeyServer,
-    search: &HashSet<Fingerprint>,
-    depth: u8,
-    result: &mut HashMap<Fingerprint, Cert>,
-) {
-    info!("Gossiping on depth:\t{},\t\tkeys:\t{}", depth, search.len());
-    let mut search_next_layer: HashSet<Fingerprint> = Default::default();
-    for fingerprint in search {
-        trace!("Gossiping key:\t{}\t\tdepth:\t{}", fingerprint, depth);
-        if result.contains_key(fingerprint) {
-            continue;
+/// Convert a cert valid under the policy into the model of the web of trust
+pub(crate) fn openpgp_key(cert: &ValidCert) -> OpenPgpKey {
+    let cert_synopsis: CertSynopsis = cert.clone().into();
+    let id = Arc::new(cert_synopsis.fingerprint().to_string());
+    let primary_id = Arc::new(
+        cert.primary_userid()
+            .map(|v| v.userid().to_string())
+            .unwrap_or_default(),
+    );
+    OpenPgpKey {
+        id: id.clone(),
+        is_revoked: cert_synopsis.revocation_status() != RevocationStatus::NotAsFarAsWeKnow,
+        revocation: revocation(&cert.revocation_status()),
+        is_expired: cert_synopsis
+            .expiration_time()
+            .map_or_else(|| false, |v| v < SystemTime::now()),
+        user_ids: cert
+            .userids()
+            .map(|user_id| {
+                let user_id_synopsis: UserIDSynopsis = user_id.clone().into();
+                let uid = Arc::new(user_id.to_string());
+                (
+                    uid.clone(),
+                    OpenPgpUid {
+                        fingerprint: id.clone(),
+                        uid: uid.clone(),
+                        name: user_id.name2().map_or_else(
+                            |e| {
+                                warn!("Invalid Name: {}", e);
+                                "".to_string()
+                            },
+                            |v| v.map_or_else(|| "".to_string(), |v| v.to_string()),
+                        ),
+                        email: user_id.email2().map_or_else(
+                            |e| {
+                                warn!("Invalid Email: {}", e);
+                                "".to_string()
+                            },
+                            |v| v.map_or_else(|| "".to_string(), |v| v.to_string()),
+                        ),
+                        comment: user_id.comment2().map_or_else(
+                            |e| {
+                                warn!("Invalid Comment: {}", e);
+                                "".to_string()
+                            },
+                            |v| v.map_or_else(|| "".to_string(), |v| v.to_string()),
+                        ),
+                        sig_vec: user_id.signatures().filter_map(openpgp_sig).collect(),
+                        is_revoked: user_id_synopsis.revocation_status()
+                            != RevocationStatus::NotAsFarAsWeKnow,
+                        revocation: revocation(&user_id.revocation_status()),
+                        is_primary: user_id.userid().to_string() == *primary_id,
+                        photo: None,
+                    },
+                )
+            })
+            .collect(),
+        user_attributes: cert
+            .user_attributes()
+            .enumerate()
+            .map(|(i, user_attribute)| {
+                let jpeg = user_attribute
+                    .user_attribute()
+                    .subpackets()
+                    .filter_map(|v| v.ok())
+                    .find_map(|v| match v {
+                        Subpacket::Image(Image::JPEG(v)) => Some(v),
+                        _ => None,
+                    });
+                let uid = Arc::new(match jpeg {
+                    Some(_) => format!("[jpeg image #{}]", i + 1),
+                    None => format!("[user attribute #{}]", i + 1),
+                });
+                (
+                    uid.clone(),
+                    OpenPgpUid {
+                        fingerprint: id.clone(),
+                        uid,
+                        name: Default::default(),
+                        email: Default::default(),
+                        comment: Default::default(),
+                        sig_vec: user_attribute
+                            .signatures()
+                            .filter_map(openpgp_sig)
+                            .collect(),
+                        is_revoked: matches!(
+                            user_attribute.revocation_status(),
+                            sequoia_openpgp::types::RevocationStatus::Revoked(_)
+                        ),
+                        revocation: revocation(&user_attribute.revocation_status()),
+                        is_primary: false,
+                        photo: jpeg.map(|v| OpenPgpPhoto {
+                            file_name: format!("{}-{}.jpg", id, i + 1),
+                            jpeg: Arc::new(v.into_vec()),
+                        }),
+                    },
+                )
+            })
+            .collect(),
+        primary_user_id: primary_id.clone(),
+        invalid_reason: None,
+        primary_key: key_material(&cert.primary_key().into()),
+        subkeys: cert
+            .keys()
+            .subkeys()
+            .map(|v| key_material(&v.into()))
+            .collect(),
+    }
+}
+
+/// The newest revocation signature, by the key itself or by a designated revoker
+fn revocation(status: &sequoia_openpgp::types::RevocationStatus) -> Option<OpenPgpSig> {
+    match status {
+        sequoia_openpgp::types::RevocationStatus::Revoked(sigs)
+        | sequoia_openpgp::types::RevocationStatus::CouldBe(sigs) => sigs
+            .iter()
+            .filter_map(|sig| openpgp_sig(sig))
+            .max_by_key(|sig| sig.creation_time),
+        sequoia_openpgp::types::RevocationStatus::NotAsFarAsWeKnow => None,
+    }
+}
+
+/// Validate a cert under the policy, a cert rejected by it is validated without policy instead,
+/// together with the reason of the rejection, to be shown as invalid
+pub(crate) fn validate_cert<'a>(
+    cert: &'a Cert,
+    policy: &'a dyn Policy,
+) -> anyhow::Result<(ValidCert<'a>, Option<anyhow::Error>)> {
+    match cert.with_policy(policy, SystemTime::now()) {
+        Ok(cert) => Ok((cert, None)),
+        Err(e) => Ok((cert.with_policy(NULL_POLICY, SystemTime::now())?, Some(e))),
+    }
+}
+
+fn openpgp_sig(sig: &Signature) -> Option<OpenPgpSig> {
+    Some(OpenPgpSig {
+        fingerprint: sig.issuer_fingerprints().next().map_or_else(
+            || {
+                warn!("Invalid Issuer: {:?}", sig);
+                "".to_string()
+            },
+            |v| v.to_string(),
+        ),
+        issuer_keyid: sig
+            .get_issuers()
+            .first()
+            .map_or_else(String::new, |v| KeyID::from(v).to_hex()),
+        uid: sig.signers_user_id().map_or_else(
+            || "".to_string(),
+            |v| {
+                String::from_utf8(Vec::from(v)).unwrap_or_else(|e| {
+                    warn!("Invalid Signer User ID: {}", e);
+                    "".to_string()
+                })
+            },
+        ),
+        trust_level: sig.trust_signature().unwrap_or((0, 0)).0,
+        trust_value: sig.trust_signature().unwrap_or((0, 0)).1.into(),
+        regex: sig
+            .regular_expressions()
+            .map(|v| String::from_utf8_lossy(v).to_string())
+            .collect(),
+        sig_type: sig.typ().into(),
+        creation_time: sig
+            .signature_creation_time()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs(),
+    })
+}
+
+/// Write the JPEG images of photo IDs into the directory
+pub(crate) fn extract_photos(
+    key_set: &HashMap<Arc<String>, OpenPgpKey>,
+    directory: &ClioPath,
+) -> anyhow::Result<()> {
+    create_dir_all(directory.path())
+        .with_context(|| format!("Failed to create directory: {}", directory.display()))?;
+    key_set
+        .values()
+        .flat_map(|pgp_key| pgp_key.user_attributes.values())
+        .filter_map(|pgp_uid| pgp_uid.photo.as_ref())
+        .try_for_each(|photo| {
+            let path = directory.path().join(&photo.file_name);
+            info!("Extracting photo ID: {}", path.display());
+            std::fs::write(&path, photo.jpeg.as_slice())
+                .with_context(|| format!("Failed to write photo ID: {}", path.display()))
+        })
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or_default()
+}
+
+fn key_material(key: &ValidErasedKeyAmalgamation<PublicParts>) -> OpenPgpKeyMaterial {
+    let mut capabilities: Vec<OpenPgpKeyCapability> = Default::default();
+    if let Some(key_flags) = key.key_flags() {
+        [
+            (key_flags.for_certification(), OpenPgpKeyCapability::Certify),
+            (key_flags.for_signing(), OpenPgpKeyCapability::Sign),
+            (
+                key_flags.for_transport_encryption(),
+                OpenPgpKeyCapability::EncryptForTransport,
+            ),
+            (
+                key_flags.for_storage_encryption(),
+                OpenPgpKeyCapability::EncryptAtRest,
+            ),
+            (
+                key_flags.for_authentication(),
+                OpenPgpKeyCapability::Authenticate,
+            ),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| *flag)
+        .for_each(|(_, capability)| capabilities.push(capability));
+    }
+    OpenPgpKeyMaterial {
+        fingerprint: key.fingerprint().to_string(),
+        algorithm: key.pk_algo().to_string(),
+        bits: key.mpis().bits(),
+        capabilities,
+        creation_time: unix_time(key.creation_time()),
+        expiration_time: key.key_expiration_time().map(unix_time),
+        is_expired: key.alive().is_err(),
+        is_revoked: matches!(
+            key.revocation_status(),
+            sequoia_openpgp::types::RevocationStatus::Revoked(_)
+        ),
+    }
+}
+
+const WEAK_ASYMMETRIC_ALGOS: [AsymmetricAlgorithm; 3] = [
+    AsymmetricAlgorithm::RSA1024,
+    AsymmetricAlgorithm::DSA1024,
+    AsymmetricAlgorithm::ElGamal1024,
+];
+
+const LEGACY_ASYMMETRIC_ALGOS: [AsymmetricAlgorithm; 8] = [
+    AsymmetricAlgorithm::DSA1024,
+    AsymmetricAlgorithm::DSA2048,
+    AsymmetricAlgorithm::DSA3072,
+    AsymmetricAlgorithm::DSA4096,
+    AsymmetricAlgorithm::ElGamal1024,
+    AsymmetricAlgorithm::ElGamal2048,
+    AsymmetricAlgorithm::ElGamal3072,
+    AsymmetricAlgorithm::ElGamal4096,
+];
+
+/// Build the policy selected by `--policy` and `--accept-sha1-until`
+pub(crate) fn policy(
+    kind: PolicyKind,
+    accept_sha1_until: Option<SystemTime>,
+) -> StandardPolicy<'static> {
+    let mut policy = StandardPolicy::new();
+    match kind {
+        PolicyKind::Strict => {
+            policy.reject_hash(HashAlgorithm::SHA1);
+            LEGACY_ASYMMETRIC_ALGOS
+                .into_iter()
+                .for_each(|v| policy.reject_asymmetric_algo(v));
         }
-        match fetch_cert_from_keyserver(keyserver, fingerprint)
-            .with_context(|| format!("Gossiping key:\t{}", fingerprint))
-        {
-            Ok(cert) => {
-                result.insert(fingerprint.clone(), cert.clone());
-                let mut issuers: HashSet<Fingerprint> = Default::default();
-                for uid in cert.userids() {
-                    for sig in uid.signatures() {
-                        issuers.extend(sig.issuer_fingerprints().cloned());
-                    }
-                }
-                info!(
-                    "Gossiping key:\t{}\t\tissuers:\t{}\tdepth:\t{}",
-                    fingerprint,
-                    issuers.len(),
-                    depth
-                );
-                if depth > 0 {
-                    search_next_layer.extend(issuers);
-                }
-            }
-            Err(err) => {
-                warn!("{:#}", err)
-            }
+        PolicyKind::Standard => {}
+        PolicyKind::Permissive => {
+            policy.accept_hash(HashAlgorithm::SHA1);
+            WEAK_ASYMMETRIC_ALGOS
+                .into_iter()
+                .for_each(|v| policy.accept_asymmetric_algo(v));
         }
     }
-    if depth > 0 {
-        fetch_cert_from_keyserver_recursive(keyserver, &search_next_layer, depth - 1, result);
+    // Only ever extend the acceptance of SHA-1, for each property on its own
+    if let Some(cutoff) = accept_sha1_until {
+        [
+            HashAlgoSecurity::CollisionResistance,
+            HashAlgoSecurity::SecondPreImageResistance,
+        ]
+        .into_iter()
+        .for_each(|property| {
+            let cutoff = policy
+                .hash_cutoff(HashAlgorithm::SHA1, property)
+                .map(|v| v.max(cutoff));
+            policy.reject_hash_property_at(HashAlgorithm::SHA1, property, cutoff);
+        });
     }
+    policy
 }
 
-pub(crate) fn fetch_cert_from_keyserver_once_lock_recursive(
-    keyserver_lock: &OnceLock<KeyServer>,
-    search: &HashSet<Fingerprint>,
-    depth: u8,
-    result: &mut HashMap<Fingerprint, Cert>,
-) {
-    match keyserver_lock.get() {
-        Some(keyserver) => fetch_cert_from_keyserver_recursive(keyserver, search, depth, result),
-        None => Default::default(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const PROPERTIES: [HashAlgoSecurity; 2] = [
+        HashAlgoSecurity::CollisionResistance,
+        HashAlgoSecurity::SecondPreImageResistance,
+    ];
+
+    fn sha1_cutoffs(policy: &StandardPolicy) -> Vec<Option<SystemTime>> {
+        PROPERTIES
+            .iter()
+            .map(|v| policy.hash_cutoff(HashAlgorithm::SHA1, *v))
+            .collect()
+    }
+
+    #[test]
+    fn early_sha1_cutoff_rejects_no_more() {
+        let early = UNIX_EPOCH + Duration::from_secs(86400);
+        [
+            PolicyKind::Strict,
+            PolicyKind::Standard,
+            PolicyKind::Permissive,
+        ]
+        .into_iter()
+        .for_each(|kind| {
+            // No cutoff means accepted forever
+            sha1_cutoffs(&policy(kind, Some(early)))
+                .into_iter()
+                .zip(sha1_cutoffs(&policy(kind, None)))
+                .for_each(|(with, without)| match (with, without) {
+                    (Some(with), Some(without)) => assert!(with >= without),
+                    (with, without) => assert!(with.is_none() || without.is_some()),
+                });
+        });
+        assert_eq!(
+            sha1_cutoffs(&policy(PolicyKind::Standard, Some(early))),
+            sha1_cutoffs(&policy(PolicyKind::Standard, None))
+        );
+        assert_eq!(
+            sha1_cutoffs(&policy(PolicyKind::Permissive, Some(early))),
+            [None, None]
+        );
+    }
+
+    #[test]
+    fn late_sha1_cutoff_extends_both_properties() {
+        let late = UNIX_EPOCH + Duration::from_secs(4102444800);
+        assert_eq!(
+            sha1_cutoffs(&policy(PolicyKind::Standard, Some(late))),
+            [Some(late), Some(late)]
+        );
     }
 }
@@ -0,0 +1,65 @@
+use crate::keyring::insert_cert;
+use anyhow::{anyhow, Context};
+use clio::ClioPath;
+use log::{info, warn};
+use sequoia_cert_store::store::openpgp_cert_d::TRUST_ROOT;
+use sequoia_cert_store::store::CertD;
+use sequoia_cert_store::Store;
+use sequoia_openpgp::parse::Parse;
+use sequoia_openpgp::{Cert, Fingerprint};
+use std::collections::HashMap;
+
+/// Load every cert in an OpenPGP cert-d (the store used by `sq`), and its local trust root if any
+pub(crate) fn read_cert_store(
+    path: &ClioPath,
+) -> anyhow::Result<(HashMap<Fingerprint, Cert>, Option<Fingerprint>)> {
+    if !path.is_local() || !path.is_dir() {
+        return Err(anyhow!("Cert store {} is not a directory", path.display()));
+    }
+    let certd = CertD::open(path.path())
+        .with_context(|| format!("Failed to open cert store: {}", path.display()))?;
+
+    let mut certs: HashMap<Fingerprint, Cert> = Default::default();
+    let mut invalid: usize = 0;
+    certd.certs().for_each(|lazy_cert| {
+        let fingerprint = lazy_cert.fingerprint();
+        match lazy_cert.to_cert() {
+            Ok(cert) => insert_cert(&mut certs, cert.clone()),
+            Err(e) => {
+                invalid += 1;
+                warn!("{}: Invalid Cert {}: {}", path.display(), fingerprint, e);
+            }
+        }
+    });
+
+    let trust_root = certd
+        .certd()
+        .get(TRUST_ROOT)
+        .with_context(|| {
+            format!(
+                "Failed to read trust root of cert store: {}",
+                path.display()
+            )
+        })?
+        .map(|(_, bytes)| Cert::from_bytes(&bytes))
+        .transpose()
+        .with_context(|| format!("Invalid trust root in cert store: {}", path.display()))?
+        .map(|cert| {
+            let fingerprint = cert.fingerprint();
+            info!("{}: Trust root: {}", path.display(), fingerprint);
+            insert_cert(&mut certs, cert.strip_secret_key_material());
+            fingerprint
+        });
+
+    if invalid > 0 {
+        warn!(
+            "{}: {} certs loaded, {} invalid",
+            path.display(),
+            certs.len(),
+            invalid
+        );
+    } else {
+        info!("{}: {} certs loaded", path.display(), certs.len());
+    }
+    Ok((certs, trust_root))
+}
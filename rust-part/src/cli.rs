@@ -1,7 +1,11 @@
-use clap::{Parser, Subcommand};
+use anyhow::{anyhow, Context};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
 use clap_verbosity_flag::Verbosity;
-use clio::{ClioPath, Input, Output};
+use clio::ClioPath;
+use serde::Deserialize;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -9,9 +13,28 @@ pub struct Cli {
     #[command(flatten)]
     pub(crate) verbose: Verbosity,
 
-    /// Import keyring from file or stdin
+    /// Read defaults of the options from this TOML file, instead of `$XDG_CONFIG_HOME/pgp-sig2dot/config.toml`
+    /// Options given on the command line take precedence over the config
     #[arg(global = true, long, value_parser)]
-    pub(crate) import: Option<Input>,
+    pub(crate) config: Option<ClioPath>,
+
+    /// Import keyring from file, directory or stdin, can be given multiple times
+    /// Directories are searched recursively for `*.asc`, `*.gpg` and `*.pgp` files
+    #[arg(global = true, long, value_parser, action = ArgAction::Append, num_args = 1)]
+    pub(crate) import: Option<Vec<ClioPath>>,
+
+    /// Import keyring from a GnuPG home directory, reading `pubring.kbx` or `pubring.gpg` without GnuPG installed
+    #[arg(global = true, long, value_parser)]
+    pub(crate) gpg_homedir: Option<ClioPath>,
+
+    /// Color nodes by the ownertrust values in `trustdb.gpg` of the GnuPG home directory
+    #[arg(global = true, long, requires = "gpg_homedir")]
+    pub(crate) gpg_ownertrust: bool,
+
+    /// Import keyring from an OpenPGP cert-d directory, the cert store used by `sq`
+    /// Its local trust root is used as a trust root for gossiping
+    #[arg(global = true, long, value_parser)]
+    pub(crate) cert_store: Option<ClioPath>,
 
     /// Import keys from keyserver by fingerprint
     #[arg(global = true, long, value_parser, short = 'k', num_args = 1..)]
@@ -22,6 +45,25 @@ pub struct Cli {
     #[arg(global = true, long, value_parser, num_args = 1..)]
     pub(crate) gossip: Option<u8>,
 
+    /// Direction of gossiping: `in` follows the issuers of certifications on the trust roots,
+    /// `out` follows the keys certified by the trust roots within the loaded keys, `both` follows both
+    #[arg(global = true, long, value_enum, default_value_t = GossipDirection::In)]
+    pub(crate) gossip_direction: GossipDirection,
+
+    /// Stop gossiping after fetching this many keys from the keyserver in this run,
+    /// keys already loaded or resumed from `--gossip-state` don't count
+    #[arg(global = true, long, value_parser)]
+    pub(crate) max_keys: Option<usize>,
+
+    /// Follow at most this many issuers of each key while gossiping
+    #[arg(global = true, long, value_parser)]
+    pub(crate) max_fanout_per_key: Option<usize>,
+
+    /// Persist the gossip frontier to this file, and the fetched keys next to it, to resume an interrupted gossip
+    /// A file written for other trust roots is started over
+    #[arg(global = true, long, value_parser)]
+    pub(crate) gossip_state: Option<ClioPath>,
+
     /// Show only primary UIDs
     #[arg(global = true, long, short = 'p')]
     pub(crate) show_primary_uid_only: bool,
@@ -30,10 +72,74 @@ pub struct Cli {
     #[arg(global = true, long, short = 'a')]
     pub(crate) show_self_sigs: bool,
 
+    /// Show issuers of certifications which are not in the keyring as gray placeholder nodes labelled by fingerprint
+    #[arg(global = true, long)]
+    pub(crate) show_unknown_issuers: bool,
+
+    /// Show user attributes, e.g. photo IDs, and their certifications as nodes labelled like `[jpeg image #1]`
+    #[arg(global = true, long)]
+    pub(crate) show_user_attributes: bool,
+
+    /// Extract the JPEG images of photo IDs into this directory as `<fingerprint>-<n>.jpg`,
+    /// and show them in the nodes of DOT, SVG and HTML by their path
+    #[arg(global = true, long, value_parser, requires = "show_user_attributes")]
+    pub(crate) extract_photos: Option<ClioPath>,
+
+    /// List the fingerprints of issuers which are not in the keyring, one per line, to pass to `--fingerprint`
+    #[arg(global = true, long)]
+    pub(crate) list_unknown_issuers: bool,
+
+    /// Show only trust signatures, which make their targets trusted introducers, e.g. the CA hierarchy of an
+    /// organization using OpenPGP CA
+    #[arg(global = true, long)]
+    pub(crate) introducers_only: bool,
+
+    /// Detect organizations using OpenPGP CA: group the members of each CA and highlight the bridges between CAs
+    /// CAs are keys with an `openpgp-ca@` UID, or keys trust-signing many UIDs within one domain
+    #[arg(global = true, long)]
+    pub(crate) openpgp_ca: bool,
+
     /// Fetch the newest signature from key server for each key in keyring
     #[arg(global = true, long)]
     pub(crate) online: bool,
 
+    /// Mark weak or problematic keys in the graph: weak algorithms, SHA-1 certifications,
+    /// no encryption subkey, near expiry and keys certified only by themselves
+    #[arg(global = true, long)]
+    pub(crate) audit: bool,
+
+    /// Warn about keys expiring within this duration when auditing, e.g. `30d`, `12w`, `1y`
+    #[arg(global = true, long, value_parser = parse_duration, default_value = "30d")]
+    pub(crate) warn_expiry: Duration,
+
+    /// Policy deciding which algorithms are acceptable: `strict` also rejects SHA-1, DSA and ElGamal,
+    /// `permissive` also accepts SHA-1 and 1024-bit keys
+    /// Certs rejected by the policy are shown as invalid nodes with the reason of rejection
+    #[arg(global = true, long, value_enum, default_value_t = PolicyKind::Standard)]
+    pub(crate) policy: PolicyKind,
+
+    /// Accept SHA-1 signatures created before this date, e.g. `2023-02-01`, to keep old webs of trust visible
+    #[arg(global = true, long, value_parser = parse_date)]
+    pub(crate) accept_sha1_until: Option<SystemTime>,
+
+    /// Output format of the graph, inferred from the extension of `--output` if not given
+    /// Raster images like PNG aren't supported, convert the SVG instead, e.g. `rsvg-convert -o wot.png wot.svg`
+    #[arg(global = true, long, value_enum)]
+    pub(crate) format: Option<OutputFormat>,
+
+    /// Write the graph to this file instead of stdout
+    /// For CSV and TSV, a path without a `.csv` or `.tsv` extension is a directory to write `nodes` and `edges` into
+    #[arg(global = true, long, short = 'o', value_parser)]
+    pub(crate) output: Option<ClioPath>,
+
+    /// Style the graph with the Graphviz attributes of node states and edge types in this TOML file
+    #[arg(global = true, long, value_parser)]
+    pub(crate) style: Option<ClioPath>,
+
+    /// For CSV and TSV, write a single edge list instead of a directory of nodes and edges
+    #[arg(global = true, long)]
+    pub(crate) edges_only: bool,
+
     /// Keep the output simple instead of JSON
     #[arg(global = true, long)]
     pub(crate) simple: bool,
@@ -46,6 +152,179 @@ pub struct Cli {
     pub(crate) command: Option<Commands>,
 }
 
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|e| format!("Invalid duration {}: {}", value, e))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration unit: {}", unit)),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Invalid duration {}: too long", value))
+}
+
+pub(crate) fn parse_date(value: &str) -> Result<SystemTime, String> {
+    let parts: Vec<i64> = value
+        .splitn(3, '-')
+        .map(|v| v.parse::<i64>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid date {}: {}", value, e))?;
+    let [year, month, day] = parts[..] else {
+        return Err(format!("Invalid date {}: expected YYYY-MM-DD", value));
+    };
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return Err(format!("Invalid date {}: expected YYYY-MM-DD", value)),
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return Err(format!(
+            "Invalid date {}: month {} has {} days",
+            value, month, days_in_month
+        ));
+    }
+    // Howard Hinnant's days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_prime = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_prime + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    u64::try_from(days)
+        .map(|v| UNIX_EPOCH + Duration::from_secs(v * 24 * 60 * 60))
+        .map_err(|_| format!("Invalid date {}: before 1970-01-01", value))
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputFormat {
+    /// Graphviz DOT
+    Dot,
+    /// JSON lists of nodes and edges
+    Json,
+    /// GraphML, for Gephi, yEd, Cytoscape or networkx
+    Graphml,
+    /// SVG laid out without Graphviz
+    Svg,
+    /// Self-contained HTML page with an interactive viewer
+    Html,
+    /// Mermaid flowchart
+    Mermaid,
+    /// PlantUML diagram
+    Plantuml,
+    /// Comma-separated nodes and edges
+    Csv,
+    /// Tab-separated nodes and edges
+    Tsv,
+}
+
+/// Extensions of raster images, which can't be written
+const RASTER_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+
+impl Cli {
+    /// Refuse to write a raster image by the extension of `--output`, instead of writing DOT into it
+    pub(crate) fn check_output_format(&self) -> anyhow::Result<()> {
+        let Some(extension) = self
+            .output
+            .as_ref()
+            .and_then(|v| v.extension())
+            .and_then(|v| v.to_str())
+            .map(|v| v.to_ascii_lowercase())
+        else {
+            return Ok(());
+        };
+        if self.format.is_none() && RASTER_EXTENSIONS.contains(&extension.as_str()) {
+            return Err(anyhow!(
+                "{} output isn't supported, write SVG and convert it, e.g. `rsvg-convert -o wot.{} wot.svg`",
+                extension.to_ascii_uppercase(),
+                extension
+            ));
+        }
+        Ok(())
+    }
+
+    /// The format given by `--format`, or inferred from the extension of `--output`
+    pub(crate) fn explicit_output_format(&self) -> Option<OutputFormat> {
+        self.format.or_else(|| {
+            let output = self.output.as_ref()?;
+            let extension = output.extension()?.to_str()?.to_ascii_lowercase();
+            match extension.as_str() {
+                "dot" | "gv" => Some(OutputFormat::Dot),
+                "svg" => Some(OutputFormat::Svg),
+                "html" | "htm" => Some(OutputFormat::Html),
+                "mmd" | "mermaid" => Some(OutputFormat::Mermaid),
+                "puml" | "plantuml" => Some(OutputFormat::Plantuml),
+                "json" => Some(OutputFormat::Json),
+                "graphml" => Some(OutputFormat::Graphml),
+                "csv" => Some(OutputFormat::Csv),
+                "tsv" => Some(OutputFormat::Tsv),
+                _ => None,
+            }
+        })
+    }
+
+    /// The format of the graph, DOT by default
+    pub(crate) fn output_format(&self) -> OutputFormat {
+        self.explicit_output_format().unwrap_or(OutputFormat::Dot)
+    }
+
+    /// Write data to `--output`, or stdout, keeping it apart from the logs on stderr
+    pub(crate) fn write_output(&self, content: &str) -> anyhow::Result<()> {
+        let output = self.output.clone().unwrap_or_else(ClioPath::std);
+        let mut writer = output
+            .clone()
+            .create()
+            .with_context(|| format!("Failed to create output: {}", output.display()))?;
+        writer.write_all(content.as_bytes())?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Whether CSV or TSV is written as a directory of nodes and edges rather than a single edge list
+    pub(crate) fn output_is_table_directory(&self) -> bool {
+        let Some(output) = &self.output else {
+            return false;
+        };
+        let is_table_file = output
+            .extension()
+            .and_then(|v| v.to_str())
+            .is_some_and(|v| ["csv", "tsv"].contains(&v.to_ascii_lowercase().as_str()));
+        !self.edges_only && output.is_local() && !is_table_file
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GossipDirection {
+    In,
+    Out,
+    Both,
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PolicyKind {
+    Strict,
+    Standard,
+    Permissive,
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub(crate) enum Commands {
     #[command(about = "Generate manual or shell auto complete file")]
@@ -53,6 +332,52 @@ pub(crate) enum Commands {
         #[command(subcommand)]
         gen_command: GenCommand,
     },
+    #[command(
+        about = "Analyze the web of trust: strong set, degree, centrality and mean shortest distance"
+    )]
+    Analyze {
+        #[arg(help = "Output the report in JSON", long)]
+        json: bool,
+        #[arg(help = "Show only the top N keys of the ranking", long)]
+        top: Option<usize>,
+    },
+    #[command(
+        about = "Suggest certifications among attendees (given by `--fingerprint`) that would most improve connectivity"
+    )]
+    Suggest {
+        #[arg(
+            help = "Rank certifications by how much they reduce the mean distance from this root to the attendees",
+            long
+        )]
+        root: Option<String>,
+        #[arg(help = "Output the suggestions in JSON", long)]
+        json: bool,
+        #[arg(help = "Show only the top N suggestions", long, default_value_t = 10)]
+        top: usize,
+    },
+    #[command(
+        about = "Print the matrix of who signed whom among attendees (given by `--fingerprint`, or all keys), as text, or CSV or HTML given by `--format`"
+    )]
+    Matrix,
+    #[command(about = "Print the audit summary of weak or problematic keys")]
+    Audit {
+        #[arg(help = "Output the audit summary in JSON", long)]
+        json: bool,
+    },
+    #[command(about = "Compare two keyrings or two snapshots of the web of trust")]
+    Diff {
+        #[arg(help = "Old keyring, file or directory")]
+        old: ClioPath,
+        #[arg(help = "New keyring, file or directory")]
+        new: ClioPath,
+        #[arg(help = "Output the differences in JSON", long, conflicts_with = "dot")]
+        json: bool,
+        #[arg(
+            help = "Output a DOT graph with added edges in green and removed edges in dashed gray",
+            long
+        )]
+        dot: bool,
+    },
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -62,10 +387,118 @@ pub(crate) enum GenCommand {
         #[arg(help = "Output Path", long)]
         path: ClioPath,
     },
-    #[command(about = "Generate shell auto complete file")]
-    Complete {
-        args: Shell,
-        #[arg(help = "Output Path", long, default_value = "-")]
-        output: Output,
-    },
+    #[command(about = "Generate shell auto complete file, written to `--output` or stdout")]
+    Complete { args: Shell },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from([&["pgp-sig2dot"], args].concat()).unwrap()
+    }
+
+    fn imports(cli: &Cli) -> Vec<String> {
+        cli.import
+            .iter()
+            .flatten()
+            .map(|v| v.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn import_can_be_repeated() {
+        let cli = parse(&["--import", "a.gpg", "--import", "b.gpg"]);
+        assert_eq!(imports(&cli), ["a.gpg", "b.gpg"]);
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn import_takes_one_value() {
+        assert!(Cli::try_parse_from(["pgp-sig2dot", "--import", "a.gpg", "b.gpg"]).is_err());
+    }
+
+    #[test]
+    fn subcommand_after_import() {
+        let cli = parse(&["--import", "a.gpg", "analyze"]);
+        assert_eq!(imports(&cli), ["a.gpg"]);
+        assert!(matches!(cli.command, Some(Commands::Analyze { .. })));
+
+        let cli = parse(&["--import", "a.gpg", "matrix"]);
+        assert!(matches!(cli.command, Some(Commands::Matrix)));
+
+        let cli = parse(&["--import", "a.gpg", "suggest", "--root", "ABCD"]);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Suggest { root: Some(ref root), .. }) if root == "ABCD"
+        ));
+    }
+
+    #[test]
+    fn global_options_after_subcommand() {
+        let cli = parse(&["audit", "--import", "a.gpg", "--json"]);
+        assert_eq!(imports(&cli), ["a.gpg"]);
+        assert!(matches!(cli.command, Some(Commands::Audit { json: true })));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90 * 86400)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("1y"), Ok(Duration::from_secs(365 * 86400)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("7s"), Ok(Duration::from_secs(7)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3x").is_err());
+        assert!(parse_duration("-1d").is_err());
+        assert!(parse_duration("18446744073709551615y").is_err());
+    }
+
+    fn days(value: &str) -> Result<u64, String> {
+        parse_date(value).map(|v| v.duration_since(UNIX_EPOCH).unwrap().as_secs() / 86400)
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(days("1970-01-01"), Ok(0));
+        assert_eq!(days("2000-02-29"), Ok(11016));
+        assert_eq!(days("2024-02-29"), Ok(19782));
+        assert_eq!(days("2024-03-01"), Ok(19783));
+        assert_eq!(days("2023-12-31"), Ok(19722));
+    }
+
+    #[test]
+    fn invalid_dates() {
+        assert!(parse_date("2024-02-30").is_err());
+        assert!(parse_date("2024-02-31").is_err());
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("1900-02-29").is_err());
+        assert!(parse_date("2024-04-31").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-00-10").is_err());
+        assert!(parse_date("2024-01-00").is_err());
+        assert!(parse_date("2024-01").is_err());
+        assert!(parse_date("2024/01/01").is_err());
+        assert!(parse_date("1969-12-31").is_err());
+    }
+
+    #[test]
+    fn raster_output_is_refused() {
+        assert!(parse(&["--output", "wot.png"])
+            .check_output_format()
+            .is_err());
+        assert!(parse(&["--output", "wot.JPG"])
+            .check_output_format()
+            .is_err());
+        assert!(parse(&["--output", "wot.svg"])
+            .check_output_format()
+            .is_ok());
+        assert!(parse(&["--output", "wot.png", "--format", "svg"])
+            .check_output_format()
+            .is_ok());
+    }
 }
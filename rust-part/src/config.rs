@@ -0,0 +1,267 @@
+use crate::cli::{parse_date, parse_duration, Cli, GossipDirection, OutputFormat, PolicyKind};
+use anyhow::{anyhow, Context};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use clio::ClioPath;
+use log::debug;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Defaults of the command line options, read from a TOML file
+/// Keys are named after the long options, e.g. `show-primary-uid-only = true`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Config {
+    import: Option<Vec<PathBuf>>,
+    gpg_homedir: Option<PathBuf>,
+    gpg_ownertrust: Option<bool>,
+    cert_store: Option<PathBuf>,
+    fingerprint: Option<Vec<String>>,
+    gossip: Option<u8>,
+    gossip_direction: Option<GossipDirection>,
+    max_keys: Option<usize>,
+    max_fanout_per_key: Option<usize>,
+    gossip_state: Option<PathBuf>,
+    show_primary_uid_only: Option<bool>,
+    show_self_sigs: Option<bool>,
+    show_unknown_issuers: Option<bool>,
+    show_user_attributes: Option<bool>,
+    extract_photos: Option<PathBuf>,
+    introducers_only: Option<bool>,
+    openpgp_ca: Option<bool>,
+    online: Option<bool>,
+    audit: Option<bool>,
+    warn_expiry: Option<String>,
+    policy: Option<PolicyKind>,
+    accept_sha1_until: Option<String>,
+    format: Option<OutputFormat>,
+    style: Option<PathBuf>,
+    simple: Option<bool>,
+    keyserver: Option<String>,
+}
+
+/// `$XDG_CONFIG_HOME/pgp-sig2dot/config.toml`, or `~/.config/pgp-sig2dot/config.toml`
+fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|v| v.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|v| PathBuf::from(v).join(".config")))?;
+    Some(config_home.join("pgp-sig2dot").join("config.toml"))
+}
+
+/// Read the file given by `--config`, or the default one if it exists
+pub(crate) fn read_config(path: Option<&ClioPath>) -> anyhow::Result<Option<(PathBuf, Config)>> {
+    let path = match path {
+        Some(path) => path.path().to_path_buf(),
+        None => match default_path().filter(|v| v.is_file()) {
+            Some(path) => path,
+            None => return Ok(None),
+        },
+    };
+    debug!("Reading config from {}", path.display());
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config: {}", path.display()))?;
+    let config = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config: {}", path.display()))?;
+    Ok(Some((path, config)))
+}
+
+/// Whether the option was given on the command line, before or after the subcommand
+fn from_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+        || matches
+            .subcommand()
+            .is_some_and(|(_, v)| from_command_line(v, id))
+}
+
+impl Config {
+    /// Fill the options which aren't given on the command line with the values of the config,
+    /// resolving relative paths against the directory of the config file
+    pub(crate) fn apply(
+        self,
+        path: &Path,
+        args: &mut Cli,
+        matches: &ArgMatches,
+    ) -> anyhow::Result<()> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let clio_path = |v: PathBuf| {
+            let path = if v.as_os_str() == "-" { v } else { dir.join(v) };
+            ClioPath::new(&path).with_context(|| format!("Invalid path: {}", path.display()))
+        };
+        let unset = |id: &str| !from_command_line(matches, id);
+
+        if let Some(v) = self.import.filter(|_| unset("import")) {
+            args.import = Some(v.into_iter().map(clio_path).collect::<Result<_, _>>()?);
+        }
+        if let Some(v) = self.gpg_homedir.filter(|_| unset("gpg_homedir")) {
+            args.gpg_homedir = Some(clio_path(v)?);
+        }
+        if let Some(v) = self.gpg_ownertrust.filter(|_| unset("gpg_ownertrust")) {
+            args.gpg_ownertrust = v;
+        }
+        if let Some(v) = self.cert_store.filter(|_| unset("cert_store")) {
+            args.cert_store = Some(clio_path(v)?);
+        }
+        if let Some(v) = self.fingerprint.filter(|_| unset("fingerprint")) {
+            args.fingerprint = Some(v);
+        }
+        if let Some(v) = self.gossip.filter(|_| unset("gossip")) {
+            args.gossip = Some(v);
+        }
+        if let Some(v) = self.gossip_direction.filter(|_| unset("gossip_direction")) {
+            args.gossip_direction = v;
+        }
+        if let Some(v) = self.max_keys.filter(|_| unset("max_keys")) {
+            args.max_keys = Some(v);
+        }
+        if let Some(v) = self
+            .max_fanout_per_key
+            .filter(|_| unset("max_fanout_per_key"))
+        {
+            args.max_fanout_per_key = Some(v);
+        }
+        if let Some(v) = self.gossip_state.filter(|_| unset("gossip_state")) {
+            args.gossip_state = Some(clio_path(v)?);
+        }
+        if let Some(v) = self
+            .show_primary_uid_only
+            .filter(|_| unset("show_primary_uid_only"))
+        {
+            args.show_primary_uid_only = v;
+        }
+        if let Some(v) = self.show_self_sigs.filter(|_| unset("show_self_sigs")) {
+            args.show_self_sigs = v;
+        }
+        if let Some(v) = self
+            .show_unknown_issuers
+            .filter(|_| unset("show_unknown_issuers"))
+        {
+            args.show_unknown_issuers = v;
+        }
+        if let Some(v) = self
+            .show_user_attributes
+            .filter(|_| unset("show_user_attributes"))
+        {
+            args.show_user_attributes = v;
+        }
+        if let Some(v) = self.extract_photos.filter(|_| unset("extract_photos")) {
+            args.extract_photos = Some(clio_path(v)?);
+        }
+        if let Some(v) = self.introducers_only.filter(|_| unset("introducers_only")) {
+            args.introducers_only = v;
+        }
+        if let Some(v) = self.openpgp_ca.filter(|_| unset("openpgp_ca")) {
+            args.openpgp_ca = v;
+        }
+        if let Some(v) = self.online.filter(|_| unset("online")) {
+            args.online = v;
+        }
+        if let Some(v) = self.audit.filter(|_| unset("audit")) {
+            args.audit = v;
+        }
+        if let Some(v) = self.warn_expiry.filter(|_| unset("warn_expiry")) {
+            args.warn_expiry = parse_duration(&v).map_err(|e| anyhow!(e))?;
+        }
+        if let Some(v) = self.policy.filter(|_| unset("policy")) {
+            args.policy = v;
+        }
+        if let Some(v) = self
+            .accept_sha1_until
+            .filter(|_| unset("accept_sha1_until"))
+        {
+            args.accept_sha1_until = Some(parse_date(&v).map_err(|e| anyhow!(e))?);
+        }
+        // The format of the config is the format of the graph, an extension of `--output` on the command line
+        // also takes precedence over it
+        if let Some(v) = self.format.filter(|_| {
+            args.command.is_none() && unset("format") && args.explicit_output_format().is_none()
+        }) {
+            args.format = Some(v);
+        }
+        if let Some(v) = self.style.filter(|_| unset("style")) {
+            args.style = Some(clio_path(v)?);
+        }
+        if let Some(v) = self.simple.filter(|_| unset("simple")) {
+            args.simple = v;
+        }
+        if let Some(v) = self.keyserver.filter(|_| unset("keyserver")) {
+            args.keyserver = v;
+        }
+
+        if args.gpg_ownertrust && args.gpg_homedir.is_none() {
+            return Err(anyhow!("`gpg-ownertrust` requires `gpg-homedir`"));
+        }
+        if args.extract_photos.is_some() && !args.show_user_attributes {
+            return Err(anyhow!("`extract-photos` requires `show-user-attributes`"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    fn apply(config: &str, args: &[&str]) -> anyhow::Result<Cli> {
+        let matches = Cli::command().try_get_matches_from([&["pgp-sig2dot"], args].concat())?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+        let config: Config = toml::from_str(config)?;
+        config.apply(
+            Path::new("/etc/pgp-sig2dot/config.toml"),
+            &mut cli,
+            &matches,
+        )?;
+        Ok(cli)
+    }
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let config =
+            "gossip = 2\nshow-primary-uid-only = true\nkeyserver = \"hkps://keys.openpgp.org\"\n";
+        let cli = apply(config, &["--gossip", "3"]).unwrap();
+        assert_eq!(cli.gossip, Some(3));
+        assert!(cli.show_primary_uid_only);
+        assert_eq!(cli.keyserver, "hkps://keys.openpgp.org");
+
+        // Also after the subcommand
+        let cli = apply(config, &["analyze", "--gossip", "1"]).unwrap();
+        assert_eq!(cli.gossip, Some(1));
+    }
+
+    #[test]
+    fn relative_paths_against_config() {
+        let cli = apply("import = [\"party.gpg\", \"-\"]\n", &[]).unwrap();
+        let imports: Vec<String> = cli
+            .import
+            .iter()
+            .flatten()
+            .map(|v| v.display().to_string())
+            .collect();
+        assert_eq!(imports, ["/etc/pgp-sig2dot/party.gpg", "-"]);
+
+        let cli = apply("import = [\"party.gpg\"]\n", &["--import", "other.gpg"]).unwrap();
+        assert_eq!(cli.import.unwrap()[0].display().to_string(), "other.gpg");
+    }
+
+    #[test]
+    fn format_yields_to_output_extension() {
+        let cli = apply("format = \"svg\"\n", &[]).unwrap();
+        assert_eq!(cli.format, Some(OutputFormat::Svg));
+
+        let cli = apply("format = \"svg\"\n", &["--output", "wot.html"]).unwrap();
+        assert_eq!(cli.output_format(), OutputFormat::Html);
+
+        let cli = apply("format = \"svg\"\n", &["matrix"]).unwrap();
+        assert_eq!(cli.format, None);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        assert!(apply("unknown-option = true\n", &[]).is_err());
+        assert!(apply("warn-expiry = \"3x\"\n", &[]).is_err());
+        assert!(apply("accept-sha1-until = \"2024-02-30\"\n", &[]).is_err());
+        assert!(apply("gpg-ownertrust = true\n", &[]).is_err());
+        assert!(apply("extract-photos = \"photos\"\n", &[]).is_err());
+    }
+}
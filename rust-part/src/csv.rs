@@ -0,0 +1,148 @@
+use crate::cert::get_pgp_uid_by_node_uid;
+use crate::cli::OutputFormat;
+use crate::structure::{GraphNodeUid, OpenPgpSig};
+use crate::GOSSIP_LAYER_MAP;
+use anyhow::Context;
+use clio::ClioPath;
+use petgraph::graphmap::DiGraphMap;
+use std::fmt::Write as _;
+use std::fs::create_dir_all;
+use std::io::Write;
+
+const NODE_HEADER: [&str; 8] = [
+    "fingerprint",
+    "uid",
+    "name",
+    "email",
+    "comment",
+    "is_revoked",
+    "is_primary",
+    "layer",
+];
+
+const EDGE_HEADER: [&str; 8] = [
+    "issuer_fingerprint",
+    "issuer_uid",
+    "target_fingerprint",
+    "target_uid",
+    "sig_type",
+    "trust_level",
+    "trust_value",
+    "creation_time",
+];
+
+fn delimiter(format: OutputFormat) -> char {
+    if format == OutputFormat::Tsv {
+        '\t'
+    } else {
+        ','
+    }
+}
+
+pub(crate) fn escape_field(value: &str, delimiter: char) -> String {
+    if value.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub(crate) fn write_row<S: AsRef<str>>(output: &mut String, row: &[S], delimiter: char) {
+    let row: Vec<String> = row
+        .iter()
+        .map(|v| escape_field(v.as_ref(), delimiter))
+        .collect();
+    writeln!(output, "{}", row.join(&delimiter.to_string())).unwrap();
+}
+
+/// One row per UID node, unknown issuers have only a fingerprint
+pub(crate) fn nodes(graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>, format: OutputFormat) -> String {
+    let delimiter = delimiter(format);
+    let mut output = String::new();
+    write_row(&mut output, &NODE_HEADER, delimiter);
+    graph.nodes().for_each(|uid| {
+        let layer = GOSSIP_LAYER_MAP
+            .get()
+            .and_then(|map| map.get(&uid.fingerprint.to_string()))
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        let row: Vec<String> = match get_pgp_uid_by_node_uid(&uid) {
+            None => vec![
+                uid.fingerprint.to_string(),
+                uid.uid.to_string(),
+                "".to_string(),
+                "".to_string(),
+                "".to_string(),
+                "".to_string(),
+                "".to_string(),
+                layer,
+            ],
+            Some(pgp_uid) => vec![
+                pgp_uid.fingerprint.to_string(),
+                pgp_uid.uid.to_string(),
+                pgp_uid.name.clone(),
+                pgp_uid.email.clone(),
+                pgp_uid.comment.clone(),
+                pgp_uid.is_revoked.to_string(),
+                pgp_uid.is_primary.to_string(),
+                layer,
+            ],
+        };
+        write_row(&mut output, &row, delimiter);
+    });
+    output
+}
+
+/// One row per certification edge, with its creation time in seconds since the epoch
+pub(crate) fn edges(graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>, format: OutputFormat) -> String {
+    let delimiter = delimiter(format);
+    let mut output = String::new();
+    write_row(&mut output, &EDGE_HEADER, delimiter);
+    graph.all_edges().for_each(|(issuer, target, sig)| {
+        write_row(
+            &mut output,
+            &[
+                issuer.fingerprint.to_string(),
+                issuer.uid.to_string(),
+                target.fingerprint.to_string(),
+                target.uid.to_string(),
+                format!("{:?}", sig.sig_type),
+                sig.trust_level.to_string(),
+                format!("{:?}", sig.trust_value),
+                sig.creation_time.to_string(),
+            ],
+            delimiter,
+        );
+    });
+    output
+}
+
+/// Write `nodes.csv` and `edges.csv` (or `.tsv`) into the directory, creating it if needed
+pub(crate) fn write_directory(
+    graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>,
+    directory: &ClioPath,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    create_dir_all(directory.path())
+        .with_context(|| format!("Failed to create directory: {}", directory.display()))?;
+    let extension = if format == OutputFormat::Tsv {
+        "tsv"
+    } else {
+        "csv"
+    };
+    [
+        ("nodes", nodes(graph, format)),
+        ("edges", edges(graph, format)),
+    ]
+    .into_iter()
+    .try_for_each(|(name, content)| -> anyhow::Result<()> {
+        let path = directory.clone().join(format!("{}.{}", name, extension));
+        let mut output = path
+            .clone()
+            .create()
+            .with_context(|| format!("Failed to create file: {}", path.display()))?;
+        output.write_all(content.as_bytes())?;
+        output.finish()?;
+        Ok(())
+    })
+}
@@ -0,0 +1,559 @@
+use crate::cert::{openpgp_key, validate_cert};
+use crate::keyring::read_keyrings;
+use crate::matrix::format_date;
+use crate::structure::{GraphNodeUid, OpenPgpKey, OpenPgpSig, SigType};
+use crate::KEY_SET_MAP;
+use clio::ClioPath;
+use log::{error, warn};
+use petgraph::dot::Dot;
+use petgraph::graphmap::DiGraphMap;
+use sequoia_openpgp::policy::Policy;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub(crate) enum Change {
+    Added,
+    Removed,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added => write!(f, "+"),
+            Change::Removed => write!(f, "-"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub(crate) struct KeyDiff {
+    pub(crate) change: Change,
+    pub(crate) fingerprint: String,
+    pub(crate) uid: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub(crate) struct CertificationDiff {
+    pub(crate) change: Change,
+    pub(crate) issuer: String,
+    pub(crate) fingerprint: String,
+    pub(crate) uid: String,
+    pub(crate) sig_type: SigType,
+    pub(crate) creation_time: u64,
+}
+
+/// A key becoming invalid, e.g. rejected by the policy, or `Removed` for a key becoming valid again
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub(crate) struct ValidityDiff {
+    pub(crate) change: Change,
+    pub(crate) fingerprint: String,
+    pub(crate) uid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct KeyringDiff {
+    pub(crate) keys: Vec<KeyDiff>,
+    pub(crate) invalid: Vec<ValidityDiff>,
+    pub(crate) user_ids: Vec<KeyDiff>,
+    pub(crate) certifications: Vec<CertificationDiff>,
+    pub(crate) revocations: Vec<CertificationDiff>,
+}
+
+/// Load a keyring into the model, keeping certs rejected by the policy as invalid
+pub(crate) fn read_key_set(
+    path: &ClioPath,
+    policy: &dyn Policy,
+) -> anyhow::Result<HashMap<Arc<String>, OpenPgpKey>> {
+    Ok(read_keyrings(std::slice::from_ref(path))?
+        .values()
+        .filter_map(|cert| match validate_cert(cert, policy) {
+            Err(e) => {
+                error!("{}: {}", cert.fingerprint(), e);
+                None
+            }
+            Ok((valid_cert, rejection)) => {
+                let mut pgp_key = openpgp_key(&valid_cert);
+                pgp_key.invalid_reason = rejection.map(|e| {
+                    warn!("{}: rejected by policy: {}", cert.fingerprint(), e);
+                    e.to_string()
+                });
+                Some((pgp_key.id.clone(), pgp_key))
+            }
+        })
+        .collect())
+}
+
+fn certifications(
+    key_set: &HashMap<Arc<String>, OpenPgpKey>,
+    show_self_sigs: bool,
+) -> BTreeSet<CertificationDiff> {
+    key_set
+        .values()
+        .flat_map(|pgp_key| pgp_key.user_ids.values())
+        .flat_map(|pgp_uid| {
+            pgp_uid
+                .sig_vec
+                .iter()
+                .filter(move |sig| show_self_sigs || sig.fingerprint != *pgp_uid.fingerprint)
+                .map(|sig| CertificationDiff {
+                    change: Change::Added,
+                    issuer: sig.fingerprint.clone(),
+                    fingerprint: pgp_uid.fingerprint.to_string(),
+                    uid: pgp_uid.uid.to_string(),
+                    sig_type: sig.sig_type,
+                    creation_time: sig.creation_time,
+                })
+        })
+        .collect()
+}
+
+/// The revocation of a key, or of one of its UIDs, by its revocation signature
+fn revocation_diff(
+    change: Change,
+    pgp_key: &OpenPgpKey,
+    uid: &str,
+    revocation: Option<&OpenPgpSig>,
+) -> CertificationDiff {
+    CertificationDiff {
+        change,
+        // A revocation without issuer fingerprint can only be verified as issued by the key itself
+        issuer: revocation
+            .map(|v| v.fingerprint.as_str())
+            .filter(|v| !v.is_empty())
+            .unwrap_or(&pgp_key.id)
+            .to_string(),
+        fingerprint: pgp_key.id.to_string(),
+        uid: uid.to_string(),
+        sig_type: SigType::Revoke,
+        creation_time: revocation.map_or(0, |v| v.creation_time),
+    }
+}
+
+/// Compare two snapshots of the web of trust
+pub(crate) fn diff(
+    old: &HashMap<Arc<String>, OpenPgpKey>,
+    new: &HashMap<Arc<String>, OpenPgpKey>,
+    show_self_sigs: bool,
+) -> KeyringDiff {
+    let mut diff: KeyringDiff = Default::default();
+
+    let key_diff = |change, pgp_key: &OpenPgpKey, uid: &str| KeyDiff {
+        change,
+        fingerprint: pgp_key.id.to_string(),
+        uid: uid.to_string(),
+    };
+
+    new.values()
+        .filter(|v| !old.contains_key(&v.id))
+        .for_each(|v| {
+            diff.keys
+                .push(key_diff(Change::Added, v, &v.primary_user_id))
+        });
+    old.values()
+        .filter(|v| !new.contains_key(&v.id))
+        .for_each(|v| {
+            diff.keys
+                .push(key_diff(Change::Removed, v, &v.primary_user_id))
+        });
+
+    new.values().for_each(|new_key| {
+        let Some(old_key) = old.get(&new_key.id) else {
+            return;
+        };
+        if new_key.invalid_reason.is_some() != old_key.invalid_reason.is_some() {
+            let (change, reason) = match &new_key.invalid_reason {
+                Some(reason) => (Change::Added, Some(reason.clone())),
+                None => (Change::Removed, old_key.invalid_reason.clone()),
+            };
+            diff.invalid.push(ValidityDiff {
+                change,
+                fingerprint: new_key.id.to_string(),
+                uid: new_key.primary_user_id.to_string(),
+                reason,
+            });
+        }
+        if new_key.revocation.is_some() != old_key.revocation.is_some() {
+            let (change, revocation) = if new_key.revocation.is_some() {
+                (Change::Added, &new_key.revocation)
+            } else {
+                (Change::Removed, &old_key.revocation)
+            };
+            diff.revocations
+                .push(revocation_diff(change, new_key, "", revocation.as_ref()));
+        }
+        new_key
+            .user_ids
+            .values()
+            .for_each(|new_uid| match old_key.user_ids.get(&new_uid.uid) {
+                None => diff
+                    .user_ids
+                    .push(key_diff(Change::Added, new_key, &new_uid.uid)),
+                Some(old_uid) if old_uid.revocation.is_some() != new_uid.revocation.is_some() => {
+                    let (change, revocation) = if new_uid.revocation.is_some() {
+                        (Change::Added, &new_uid.revocation)
+                    } else {
+                        (Change::Removed, &old_uid.revocation)
+                    };
+                    diff.revocations.push(revocation_diff(
+                        change,
+                        new_key,
+                        &new_uid.uid,
+                        revocation.as_ref(),
+                    ));
+                }
+                Some(_) => {}
+            });
+        old_key
+            .user_ids
+            .values()
+            .filter(|old_uid| !new_key.user_ids.contains_key(&old_uid.uid))
+            .for_each(|old_uid| {
+                diff.user_ids
+                    .push(key_diff(Change::Removed, old_key, &old_uid.uid))
+            });
+    });
+
+    let old_certifications = certifications(old, show_self_sigs);
+    let new_certifications = certifications(new, show_self_sigs);
+    new_certifications
+        .difference(&old_certifications)
+        .cloned()
+        .chain(
+            old_certifications
+                .difference(&new_certifications)
+                .map(|v| CertificationDiff {
+                    change: Change::Removed,
+                    ..v.clone()
+                }),
+        )
+        .for_each(|v| {
+            if v.sig_type == SigType::Revoke {
+                diff.revocations.push(v);
+            } else {
+                diff.certifications.push(v);
+            }
+        });
+
+    diff.keys.sort();
+    diff.invalid.sort();
+    diff.user_ids.sort();
+    diff.certifications.sort();
+    // A UID revocation also shows up as a changed self-signature with `show_self_sigs`
+    diff.revocations.sort();
+    diff.revocations.dedup();
+    diff
+}
+
+impl Display for KeyringDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.keys
+            .iter()
+            .try_for_each(|v| writeln!(f, "{} key\t\t{}\t{}", v.change, v.fingerprint, v.uid))?;
+        self.invalid.iter().try_for_each(|v| {
+            write!(f, "{} invalid\t{}\t{}", v.change, v.fingerprint, v.uid)?;
+            match &v.reason {
+                Some(reason) => writeln!(f, "\t({})", reason),
+                None => writeln!(f),
+            }
+        })?;
+        self.user_ids
+            .iter()
+            .try_for_each(|v| writeln!(f, "{} uid\t\t{}\t{}", v.change, v.fingerprint, v.uid))?;
+        self.certifications.iter().try_for_each(|v| {
+            writeln!(
+                f,
+                "{} certification\t{} -> {}\t{}\t({:?} {})",
+                v.change,
+                v.issuer,
+                v.fingerprint,
+                v.uid,
+                v.sig_type,
+                format_date(v.creation_time)
+            )
+        })?;
+        self.revocations.iter().try_for_each(|v| {
+            if v.creation_time == 0 {
+                writeln!(f, "{} revocation\t{}\t{}", v.change, v.fingerprint, v.uid)
+            } else {
+                writeln!(
+                    f,
+                    "{} revocation\t{} -> {}\t{}\t({})",
+                    v.change,
+                    v.issuer,
+                    v.fingerprint,
+                    v.uid,
+                    format_date(v.creation_time)
+                )
+            }
+        })
+    }
+}
+
+/// A certification edge of the diff graph, with its change if it was added or removed
+#[derive(Debug, Clone)]
+pub(crate) struct DiffSig<'a> {
+    pub(crate) sig: &'a OpenPgpSig,
+    pub(crate) change: Option<Change>,
+}
+
+impl Display for DiffSig<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.sig.fmt(f)
+    }
+}
+
+/// Render both snapshots in one graph, added edges in green and removed edges in dashed gray
+pub(crate) fn diff_dot(
+    old: &HashMap<Arc<String>, OpenPgpKey>,
+    new: &HashMap<Arc<String>, OpenPgpKey>,
+    diff: &KeyringDiff,
+    show_self_sigs: bool,
+) -> String {
+    let mut key_set = old.clone();
+    key_set.extend(new.iter().map(|(k, v)| (k.clone(), v.clone())));
+    KEY_SET_MAP.set(key_set).ok();
+    let Some(key_set) = KEY_SET_MAP.get() else {
+        return Default::default();
+    };
+
+    let changes: HashMap<(&str, &str, &str, u64), Change> = diff
+        .certifications
+        .iter()
+        .chain(diff.revocations.iter())
+        .map(|v| {
+            (
+                (
+                    v.issuer.as_str(),
+                    v.fingerprint.as_str(),
+                    v.uid.as_str(),
+                    v.creation_time,
+                ),
+                v.change,
+            )
+        })
+        .collect();
+    let key_changes: HashMap<&str, Change> = diff
+        .keys
+        .iter()
+        .map(|v| (v.fingerprint.as_str(), v.change))
+        .collect();
+
+    let mut graph: DiGraphMap<GraphNodeUid, DiffSig> = DiGraphMap::new();
+    let mut nodes: Vec<GraphNodeUid> = key_set
+        .values()
+        .flat_map(|pgp_key| pgp_key.user_ids.values())
+        .map(|pgp_uid| pgp_uid.into())
+        .collect();
+    nodes.sort();
+    nodes.into_iter().for_each(|node| {
+        graph.add_node(node);
+    });
+
+    let mut edges: Vec<(GraphNodeUid, GraphNodeUid, DiffSig)> = Default::default();
+    [old, new].into_iter().for_each(|snapshot| {
+        snapshot.values().for_each(|pgp_key| {
+            pgp_key.user_ids.values().for_each(|pgp_uid| {
+                pgp_uid.sig_vec.iter().for_each(|sig| {
+                    if !show_self_sigs && sig.fingerprint == *pgp_uid.fingerprint {
+                        return;
+                    }
+                    key_set.get(&sig.fingerprint).inspect(|issuer| {
+                        issuer
+                            .user_ids
+                            .get(&issuer.primary_user_id)
+                            .inspect(|sig_uid| {
+                                let change = changes
+                                    .get(&(
+                                        sig.fingerprint.as_str(),
+                                        pgp_uid.fingerprint.as_str(),
+                                        pgp_uid.uid.as_str(),
+                                        sig.creation_time,
+                                    ))
+                                    .copied();
+                                edges.push((
+                                    sig_uid.into(),
+                                    pgp_uid.into(),
+                                    DiffSig { sig, change },
+                                ));
+                            });
+                    });
+                });
+            });
+        });
+    });
+    // Added edges take precedence over removed ones, which take precedence over unchanged ones
+    edges.sort_by_key(|(issuer, target, v)| {
+        let change = match v.change {
+            None => 0,
+            Some(Change::Removed) => 1,
+            Some(Change::Added) => 2,
+        };
+        (
+            change,
+            *issuer,
+            *target,
+            v.sig.creation_time,
+            v.sig.sig_type,
+        )
+    });
+    edges.into_iter().for_each(|(issuer, target, sig)| {
+        graph.add_edge(issuer, target, sig);
+    });
+
+    let node_attr = &|_, (_, uid): (_, &GraphNodeUid)| {
+        match key_changes.get(uid.fingerprint) {
+            Some(Change::Added) => " color = green ",
+            Some(Change::Removed) => " style = dashed color = gray ",
+            None => "",
+        }
+        .to_string()
+    };
+    let edge_attr = &|_, (_, _, v): (_, _, &DiffSig)| {
+        match v.change {
+            Some(Change::Added) => " color = green ",
+            Some(Change::Removed) => " style = dashed color = gray ",
+            None if v.sig.sig_type == SigType::Revoke => " color = red ",
+            None => "",
+        }
+        .to_string()
+    };
+    let dot = Dot::with_attr_getters(&graph, &[], edge_attr, node_attr);
+    format!("{}", dot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{generate, key_set, signer, temp_path, POLICY};
+    use sequoia_openpgp::cert::{CertBuilder, CertRevocationBuilder, UserIDRevocationBuilder};
+    use sequoia_openpgp::policy::StandardPolicy;
+    use sequoia_openpgp::serialize::Serialize as _;
+    use sequoia_openpgp::types::{ReasonForRevocation, RevocationKey};
+
+    #[test]
+    fn added_and_removed_keys() {
+        let alice = generate("alice");
+        let bob = generate("bob");
+
+        let diff = diff(&key_set(&[&alice]), &key_set(&[&bob]), false);
+        assert_eq!(
+            diff.keys,
+            [
+                KeyDiff {
+                    change: Change::Added,
+                    fingerprint: bob.fingerprint().to_string(),
+                    uid: "bob".to_string(),
+                },
+                KeyDiff {
+                    change: Change::Removed,
+                    fingerprint: alice.fingerprint().to_string(),
+                    uid: "alice".to_string(),
+                },
+            ]
+        );
+        assert!(diff.revocations.is_empty());
+    }
+
+    #[test]
+    fn uid_revocation_has_its_time() {
+        let old = CertBuilder::new()
+            .add_userid("alice")
+            .add_userid("alice old")
+            .generate()
+            .unwrap()
+            .0;
+        let uid = old
+            .userids()
+            .find(|v| v.userid().value() == b"alice old")
+            .unwrap()
+            .userid()
+            .clone();
+        let sig = UserIDRevocationBuilder::new()
+            .set_reason_for_revocation(ReasonForRevocation::UIDRetired, b"")
+            .unwrap()
+            .build(&mut signer(&old), &old, &uid, None)
+            .unwrap();
+        let creation_time = sig
+            .signature_creation_time()
+            .unwrap()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let new = old.clone().insert_packets(sig).unwrap();
+
+        let diff = diff(&key_set(&[&old]), &key_set(&[&new]), true);
+        assert_eq!(
+            diff.revocations,
+            [CertificationDiff {
+                change: Change::Added,
+                issuer: old.fingerprint().to_string(),
+                fingerprint: old.fingerprint().to_string(),
+                uid: "alice old".to_string(),
+                sig_type: SigType::Revoke,
+                creation_time,
+            }]
+        );
+        assert!(diff.to_string().contains(&format_date(creation_time)));
+    }
+
+    #[test]
+    fn key_revocation_by_designated_revoker() {
+        let revoker = generate("revoker");
+        let old = CertBuilder::new()
+            .add_userid("alice")
+            .set_revocation_keys(vec![RevocationKey::new(
+                revoker.primary_key().pk_algo(),
+                revoker.fingerprint(),
+                false,
+            )])
+            .generate()
+            .unwrap()
+            .0;
+        let sig = CertRevocationBuilder::new()
+            .set_reason_for_revocation(ReasonForRevocation::KeyCompromised, b"")
+            .unwrap()
+            .build(&mut signer(&revoker), &old, None)
+            .unwrap();
+        let new = old.clone().insert_packets(sig).unwrap();
+
+        let diff = diff(&key_set(&[&old]), &key_set(&[&new]), false);
+        assert_eq!(diff.revocations.len(), 1);
+        assert_eq!(diff.revocations[0].change, Change::Added);
+        assert_eq!(
+            diff.revocations[0].issuer,
+            revoker.fingerprint().to_string()
+        );
+        assert_eq!(
+            diff.revocations[0].fingerprint,
+            old.fingerprint().to_string()
+        );
+        assert_ne!(diff.revocations[0].creation_time, 0);
+    }
+
+    #[test]
+    fn key_rejected_by_policy_becomes_invalid() {
+        let alice = generate("alice");
+        let path = temp_path("diff.pgp");
+        alice
+            .serialize(&mut std::fs::File::create(&path).unwrap())
+            .unwrap();
+        let mut policy = StandardPolicy::new();
+        policy.reject_all_asymmetric_algos();
+
+        let old = read_key_set(&ClioPath::new(&path).unwrap(), POLICY).unwrap();
+        let new = read_key_set(&ClioPath::new(&path).unwrap(), &policy).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(new.values().all(|v| v.invalid_reason.is_some()));
+
+        let diff = diff(&old, &new, false);
+        assert!(diff.keys.is_empty());
+        assert_eq!(diff.invalid.len(), 1);
+        assert_eq!(diff.invalid[0].change, Change::Added);
+        assert_eq!(diff.invalid[0].fingerprint, alice.fingerprint().to_string());
+    }
+}
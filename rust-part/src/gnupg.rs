@@ -0,0 +1,261 @@
+use crate::keyring::{insert_cert, read_keyring_file};
+use crate::structure::OpenPgpValidity;
+use anyhow::{anyhow, Context};
+use clio::ClioPath;
+use log::{debug, info, trace, warn};
+use sequoia_openpgp::parse::Parse;
+use sequoia_openpgp::{Cert, Fingerprint};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+const KEYBOX_BLOB_TYPE_HEADER: u8 = 1;
+const KEYBOX_BLOB_TYPE_OPENPGP: u8 = 2;
+
+const TRUSTDB_RECORD_LEN: usize = 40;
+const TRUSTDB_RECORD_TYPE_TRUST: u8 = 12;
+const TRUSTDB_OWNERTRUST_MASK: u8 = 0x0f;
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset + 4)
+        .map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+}
+
+/// Read a GnuPG keybox (`pubring.kbx`)
+pub(crate) fn read_keybox(
+    path: &Path,
+    certs: &mut HashMap<Fingerprint, Cert>,
+) -> anyhow::Result<()> {
+    let keybox = std::fs::read(path)
+        .with_context(|| format!("Failed to read keybox: {}", path.display()))?;
+    parse_keybox(path, &keybox, certs)
+}
+
+/// Parse the blobs of a keybox, only OpenPGP blobs are taken into account
+fn parse_keybox(
+    path: &Path,
+    keybox: &[u8],
+    certs: &mut HashMap<Fingerprint, Cert>,
+) -> anyhow::Result<()> {
+    let mut offset: usize = 0;
+    let mut valid: usize = 0;
+    let mut invalid: usize = 0;
+    while offset < keybox.len() {
+        let blob_len = read_u32(keybox, offset).ok_or(anyhow!(
+            "{}: Truncated blob at {}",
+            path.display(),
+            offset
+        ))? as usize;
+        let blob = offset
+            .checked_add(blob_len)
+            .and_then(|end| keybox.get(offset..end))
+            .filter(|_| blob_len >= 6)
+            .ok_or(anyhow!("{}: Invalid blob at {}", path.display(), offset))?;
+        trace!(
+            "Keybox blob: offset {}, length {}, type {}",
+            offset,
+            blob_len,
+            blob[4]
+        );
+
+        match blob[4] {
+            KEYBOX_BLOB_TYPE_HEADER => {}
+            KEYBOX_BLOB_TYPE_OPENPGP => {
+                // Offset and length of the keyblock are untrusted, so they may overflow
+                let keyblock = match read_u32(blob, 8).zip(read_u32(blob, 12)) {
+                    Some((start, len)) => match start.checked_add(len) {
+                        Some(end) => blob.get(start as usize..end as usize),
+                        None => {
+                            invalid += 1;
+                            warn!(
+                                "{}: Keyblock overflows at {}: start {}, length {}",
+                                path.display(),
+                                offset,
+                                start,
+                                len
+                            );
+                            offset += blob_len;
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                match keyblock.map(Cert::from_bytes) {
+                    Some(Ok(cert)) => {
+                        valid += 1;
+                        insert_cert(certs, cert);
+                    }
+                    Some(Err(e)) => {
+                        invalid += 1;
+                        warn!("{}: Invalid Cert at {}: {}", path.display(), offset, e);
+                    }
+                    None => {
+                        invalid += 1;
+                        warn!("{}: Invalid keyblock at {}", path.display(), offset);
+                    }
+                }
+            }
+            v => debug!("{}: Skip blob type {} at {}", path.display(), v, offset),
+        }
+        offset += blob_len;
+    }
+
+    if invalid > 0 {
+        warn!(
+            "{}: {} certs loaded, {} invalid",
+            path.display(),
+            valid,
+            invalid
+        );
+    } else {
+        info!("{}: {} certs loaded", path.display(), valid);
+    }
+    Ok(())
+}
+
+/// Load the public keyring of a GnuPG home directory, preferring `pubring.kbx` over `pubring.gpg`
+pub(crate) fn read_gnupg_home(homedir: &ClioPath) -> anyhow::Result<HashMap<Fingerprint, Cert>> {
+    let mut certs: HashMap<Fingerprint, Cert> = Default::default();
+    let keybox = homedir.path().join("pubring.kbx");
+    let keyring = ClioPath::local(homedir.path().join("pubring.gpg"));
+    if keybox.is_file() {
+        read_keybox(&keybox, &mut certs)?;
+    } else if keyring.is_file() {
+        read_keyring_file(&keyring, &mut certs)?;
+    } else {
+        return Err(anyhow!(
+            "Neither pubring.kbx nor pubring.gpg found in {}",
+            homedir.display()
+        ));
+    }
+    Ok(certs)
+}
+
+fn ownertrust_to_validity(ownertrust: u8) -> OpenPgpValidity {
+    match ownertrust & TRUSTDB_OWNERTRUST_MASK {
+        2 => OpenPgpValidity::Undefined,
+        3 => OpenPgpValidity::Never,
+        4 => OpenPgpValidity::Marginal,
+        5 => OpenPgpValidity::Full,
+        6 => OpenPgpValidity::Ultimate,
+        _ => OpenPgpValidity::Unknown,
+    }
+}
+
+/// Read the ownertrust values assigned by the owner from `trustdb.gpg`
+pub(crate) fn read_ownertrust(
+    homedir: &ClioPath,
+) -> anyhow::Result<HashMap<Arc<String>, OpenPgpValidity>> {
+    let path = homedir.path().join("trustdb.gpg");
+    let trustdb = std::fs::read(&path)
+        .with_context(|| format!("Failed to read trustdb: {}", path.display()))?;
+
+    let ownertrust: HashMap<Arc<String>, OpenPgpValidity> = trustdb
+        .chunks_exact(TRUSTDB_RECORD_LEN)
+        .filter(|record| record[0] == TRUSTDB_RECORD_TYPE_TRUST)
+        .filter_map(|record| {
+            let fingerprint = Fingerprint::from_bytes(&record[2..22]);
+            let validity = ownertrust_to_validity(record[22]);
+            trace!(
+                "Ownertrust: {} {:?}, depth {}",
+                fingerprint,
+                validity,
+                record[23]
+            );
+            if validity == OpenPgpValidity::Unknown {
+                None
+            } else {
+                Some((Arc::new(fingerprint.to_string()), validity))
+            }
+        })
+        .collect();
+
+    info!(
+        "{}: {} ownertrust values loaded",
+        path.display(),
+        ownertrust.len()
+    );
+    Ok(ownertrust)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sequoia_openpgp::cert::CertBuilder;
+    use sequoia_openpgp::serialize::MarshalInto;
+
+    fn blob(blob_type: u8, keyblock_start: u32, keyblock_len: u32, keyblock: &[u8]) -> Vec<u8> {
+        let mut blob = vec![0; 16];
+        blob[4] = blob_type;
+        blob[5] = 1;
+        blob[8..12].copy_from_slice(&keyblock_start.to_be_bytes());
+        blob[12..16].copy_from_slice(&keyblock_len.to_be_bytes());
+        blob.extend_from_slice(keyblock);
+        let len = blob.len() as u32;
+        blob[0..4].copy_from_slice(&len.to_be_bytes());
+        blob
+    }
+
+    fn parse(keybox: &[u8]) -> anyhow::Result<HashMap<Fingerprint, Cert>> {
+        let mut certs = Default::default();
+        parse_keybox(Path::new("pubring.kbx"), keybox, &mut certs)?;
+        Ok(certs)
+    }
+
+    #[test]
+    fn keybox_openpgp_blob() {
+        let (cert, _) = CertBuilder::new()
+            .add_userid("alice <alice@example.org>")
+            .generate()
+            .unwrap();
+        let keyblock = cert.to_vec().unwrap();
+        let keybox = [
+            blob(KEYBOX_BLOB_TYPE_HEADER, 0, 0, &[]),
+            blob(
+                KEYBOX_BLOB_TYPE_OPENPGP,
+                16,
+                keyblock.len() as u32,
+                &keyblock,
+            ),
+            blob(3, 0, 0, &[]),
+        ]
+        .concat();
+
+        let certs = parse(&keybox).unwrap();
+        assert_eq!(certs.len(), 1);
+        assert!(certs.contains_key(&cert.fingerprint()));
+    }
+
+    #[test]
+    fn keybox_invalid_keyblock_is_skipped() {
+        let keybox = [
+            blob(KEYBOX_BLOB_TYPE_OPENPGP, u32::MAX, 2, &[]),
+            blob(KEYBOX_BLOB_TYPE_OPENPGP, 16, 64, &[0; 8]),
+            blob(KEYBOX_BLOB_TYPE_OPENPGP, 16, 8, &[0; 8]),
+        ]
+        .concat();
+        assert!(parse(&keybox).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keybox_truncated() {
+        let mut keybox = blob(KEYBOX_BLOB_TYPE_HEADER, 0, 0, &[]);
+        keybox.truncate(10);
+        assert!(parse(&keybox).is_err());
+
+        let mut keybox = blob(KEYBOX_BLOB_TYPE_HEADER, 0, 0, &[]);
+        keybox[0..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(parse(&keybox).is_err());
+
+        assert!(parse(&[0, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn ownertrust_values() {
+        assert_eq!(ownertrust_to_validity(0), OpenPgpValidity::Unknown);
+        assert_eq!(ownertrust_to_validity(3), OpenPgpValidity::Never);
+        assert_eq!(ownertrust_to_validity(6), OpenPgpValidity::Ultimate);
+        // The flags above the mask are ignored
+        assert_eq!(ownertrust_to_validity(0x25), OpenPgpValidity::Full);
+    }
+}
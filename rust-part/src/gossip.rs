@@ -0,0 +1,445 @@
+use crate::cert::fetch_cert_from_keyserver;
+use crate::cli::GossipDirection;
+use crate::keyring::{insert_cert, read_keyring_file};
+use crate::structure::OpenPgpKey;
+use anyhow::Context;
+use clio::ClioPath;
+use log::{debug, info, trace, warn};
+use sequoia_net::KeyServer;
+use sequoia_openpgp::serialize::Serialize as _;
+use sequoia_openpgp::{Cert, Fingerprint};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Keys gossiped between two writes of the frontier file
+const STATE_SAVE_INTERVAL: usize = 64;
+
+/// Limits of a gossip crawl
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GossipLimit {
+    pub(crate) depth: u8,
+    pub(crate) max_keys: Option<usize>,
+    pub(crate) max_fanout_per_key: Option<usize>,
+}
+
+/// Progress of a gossip crawl, persisted to the frontier file so that an interrupted crawl can be resumed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct GossipState {
+    /// Trust roots the crawl started from, a state of other roots isn't resumed
+    #[serde(default)]
+    pub(crate) roots: Vec<String>,
+    pub(crate) layer: u8,
+    pub(crate) frontier: VecDeque<String>,
+    pub(crate) next_frontier: Vec<String>,
+    pub(crate) visited: HashSet<String>,
+    pub(crate) fetched: BTreeMap<u8, usize>,
+    pub(crate) failed: BTreeMap<u8, Vec<String>>,
+}
+
+/// Breadth-first crawler over the issuers of certifications, fetching each key from the keyserver at most once
+pub(crate) struct GossipCrawler<'a> {
+    keyserver: &'a KeyServer,
+    limit: GossipLimit,
+    state: GossipState,
+    state_path: Option<PathBuf>,
+    /// Keys gossiped since the frontier file was last written
+    unsaved: usize,
+    /// Keys fetched from the keyserver in this run, counted against `max_keys`
+    downloaded: usize,
+    result: HashMap<Fingerprint, Cert>,
+}
+
+impl<'a> GossipCrawler<'a> {
+    pub(crate) fn new(keyserver: &'a KeyServer, limit: GossipLimit, roots: &[Fingerprint]) -> Self {
+        let mut roots: Vec<String> = roots.iter().map(|v| v.to_string()).collect();
+        roots.sort();
+        roots.dedup();
+        GossipCrawler {
+            keyserver,
+            limit,
+            state: GossipState {
+                roots,
+                ..Default::default()
+            },
+            state_path: None,
+            unsaved: 0,
+            downloaded: 0,
+            result: Default::default(),
+        }
+    }
+
+    /// Persist the frontier to `path`, and the fetched keys to `path` with an additional `.pgp` extension.
+    /// If the frontier file already exists and was written for the same trust roots, the crawl is resumed from it,
+    /// otherwise the crawl starts over.
+    pub(crate) fn with_state_file(mut self, path: &ClioPath) -> anyhow::Result<Self> {
+        let state_path = path.path().to_path_buf();
+        let keyring_path = Self::keyring_path(&state_path);
+        if state_path.is_file() {
+            let content = std::fs::read(&state_path).with_context(|| {
+                format!("Failed to read gossip state: {}", state_path.display())
+            })?;
+            let state: GossipState = serde_json::from_slice(&content)
+                .with_context(|| format!("Invalid gossip state: {}", state_path.display()))?;
+            if state.roots != self.state.roots {
+                warn!(
+                    "{}: Gossip state of other trust roots ({}), starting over",
+                    state_path.display(),
+                    state.roots.join(", ")
+                );
+                if keyring_path.is_file() {
+                    std::fs::remove_file(&keyring_path).with_context(|| {
+                        format!(
+                            "Failed to remove gossip keyring: {}",
+                            keyring_path.display()
+                        )
+                    })?;
+                }
+                self.state_path = Some(state_path);
+                return Ok(self);
+            }
+            self.state = state;
+            let keyring = ClioPath::local(keyring_path);
+            if keyring.is_file() {
+                read_keyring_file(&keyring, &mut self.result)?;
+            }
+            info!(
+                "Resuming gossip from {} on layer {}, {} keys fetched, {} keys in frontier",
+                state_path.display(),
+                self.state.layer,
+                self.result.len(),
+                self.state.frontier.len()
+            );
+        }
+        self.state_path = Some(state_path);
+        Ok(self)
+    }
+
+    fn keyring_path(state_path: &std::path::Path) -> PathBuf {
+        let mut path = state_path.as_os_str().to_os_string();
+        path.push(".pgp");
+        path.into()
+    }
+
+    fn is_resumed(&self) -> bool {
+        !self.state.visited.is_empty()
+    }
+
+    fn save_state(&mut self) {
+        self.unsaved = 0;
+        let Some(state_path) = &self.state_path else {
+            return;
+        };
+        serde_json::to_vec(&self.state)
+            .map_err(anyhow::Error::from)
+            .and_then(|v| std::fs::write(state_path, v).map_err(anyhow::Error::from))
+            .with_context(|| format!("Failed to save gossip state: {}", state_path.display()))
+            .err()
+            .inspect(|e| warn!("{:#}", e));
+    }
+
+    fn save_cert(&self, cert: &Cert) {
+        let Some(state_path) = &self.state_path else {
+            return;
+        };
+        let keyring_path = Self::keyring_path(state_path);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&keyring_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut v| cert.serialize(&mut v))
+            .with_context(|| format!("Failed to save gossip keyring: {}", keyring_path.display()))
+            .err()
+            .inspect(|e| warn!("{:#}", e));
+    }
+
+    /// Use a cert that has already been fetched in this run instead of fetching it again
+    pub(crate) fn seed(&mut self, cert: Cert) {
+        self.result.insert(cert.fingerprint(), cert);
+    }
+
+    fn fetch(&mut self, fingerprint: &Fingerprint) -> anyhow::Result<Cert> {
+        if let Some(cert) = self.result.get(fingerprint) {
+            return Ok(cert.clone());
+        }
+        let cert = fetch_cert_from_keyserver(self.keyserver, fingerprint)
+            .with_context(|| format!("Gossiping key:\t{}", fingerprint))?;
+        self.downloaded += 1;
+        self.save_cert(&cert);
+        insert_cert(&mut self.result, cert.clone());
+        Ok(cert)
+    }
+
+    fn issuers(&self, cert: &Cert) -> Vec<String> {
+        let mut seen: HashSet<String> = Default::default();
+        let issuers = cert
+            .userids()
+            .flat_map(|uid| uid.signatures())
+            .flat_map(|sig| sig.issuer_fingerprints())
+            .map(|v| v.to_string())
+            .filter(|v| !self.state.visited.contains(v) && seen.insert(v.clone()));
+        match self.limit.max_fanout_per_key {
+            None => issuers.collect(),
+            Some(max_fanout) => issuers.take(max_fanout).collect(),
+        }
+    }
+
+    /// Whether fetching another key from the keyserver would exceed `max_keys`,
+    /// keys seeded or read from the frontier file don't count
+    fn is_full(&self) -> bool {
+        self.limit
+            .max_keys
+            .is_some_and(|max_keys| self.downloaded >= max_keys)
+    }
+
+    /// Crawl from the trust roots, layer by layer, until the depth limit or the key limit is reached
+    pub(crate) fn crawl(mut self) -> HashMap<Fingerprint, Cert> {
+        if !self.is_resumed() {
+            self.state.frontier = self.state.roots.iter().cloned().collect();
+        }
+
+        'layers: while !self.state.frontier.is_empty() {
+            let layer = self.state.layer;
+            info!(
+                "Gossiping on layer:\t{},\t\tkeys:\t{}",
+                layer,
+                self.state.frontier.len()
+            );
+
+            while let Some(fingerprint) = self.state.frontier.pop_front() {
+                if self.state.visited.contains(&fingerprint) {
+                    continue;
+                }
+                let parsed = Fingerprint::from_hex(&fingerprint);
+                if self.is_full() && !parsed.as_ref().is_ok_and(|v| self.result.contains_key(v)) {
+                    warn!(
+                        "Gossip stopped: reached the limit of {} keys",
+                        self.limit.max_keys.unwrap_or_default()
+                    );
+                    self.state.frontier.push_front(fingerprint);
+                    break 'layers;
+                }
+                self.state.visited.insert(fingerprint.clone());
+                trace!("Gossiping key:\t{}\t\tlayer:\t{}", fingerprint, layer);
+
+                match parsed.and_then(|v| self.fetch(&v)) {
+                    Ok(cert) => {
+                        *self.state.fetched.entry(layer).or_default() += 1;
+                        let issuers = self.issuers(&cert);
+                        info!(
+                            "Gossiping key:\t{}\t\tissuers:\t{}\tlayer:\t{}",
+                            fingerprint,
+                            issuers.len(),
+                            layer
+                        );
+                        if layer < self.limit.depth {
+                            self.state.next_frontier.extend(issuers);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("{:#}", e);
+                        self.state
+                            .failed
+                            .entry(layer)
+                            .or_default()
+                            .push(fingerprint);
+                    }
+                }
+                // Fetched keys are appended to the keyring right away, so after an interruption between two
+                // writes, a resumed crawl visits them again without fetching
+                self.unsaved += 1;
+                if self.unsaved >= STATE_SAVE_INTERVAL {
+                    self.save_state();
+                }
+            }
+
+            self.state.layer += 1;
+            self.state.frontier = std::mem::take(&mut self.state.next_frontier).into();
+            self.save_state();
+        }
+
+        if self.unsaved > 0 {
+            self.save_state();
+        }
+        self.summary();
+        self.result
+    }
+
+    fn summary(&self) {
+        (0..=self.state.layer).for_each(|layer| {
+            let fetched = self.state.fetched.get(&layer).copied().unwrap_or(0);
+            match self.state.failed.get(&layer) {
+                Some(failed) if !failed.is_empty() => {
+                    warn!(
+                        "Gossip layer {}: {} keys fetched, {} failed: {}",
+                        layer,
+                        fetched,
+                        failed.len(),
+                        failed.join(", ")
+                    );
+                }
+                _ if fetched > 0 => info!("Gossip layer {}: {} keys fetched", layer, fetched),
+                _ => {}
+            }
+        });
+    }
+}
+
+/// Compute the gossip layer of each key reachable from the trust roots over the loaded key set
+pub(crate) fn gossip_layers(
+    key_set: &HashMap<Arc<String>, OpenPgpKey>,
+    roots: &[Fingerprint],
+    direction: GossipDirection,
+) -> HashMap<Arc<String>, u8> {
+    let mut incoming: HashMap<Arc<String>, HashSet<Arc<String>>> = Default::default();
+    let mut outgoing: HashMap<Arc<String>, HashSet<Arc<String>>> = Default::default();
+    key_set.iter().for_each(|(fingerprint, pgp_key)| {
+        pgp_key.user_ids.values().for_each(|pgp_uid| {
+            pgp_uid
+                .sig_vec
+                .iter()
+                .filter(|sig| !sig.fingerprint.is_empty() && sig.fingerprint != **fingerprint)
+                .for_each(|sig| {
+                    let issuer: Arc<String> = Arc::new(sig.fingerprint.clone());
+                    incoming
+                        .entry(fingerprint.clone())
+                        .or_default()
+                        .insert(issuer.clone());
+                    outgoing
+                        .entry(issuer)
+                        .or_default()
+                        .insert(fingerprint.clone());
+                })
+        })
+    });
+
+    let mut gossip_layer_map: HashMap<Arc<String>, u8> = Default::default();
+    let mut layer: HashSet<Arc<String>> = roots
+        .iter()
+        .map(|fingerprint| Arc::new(fingerprint.to_string()))
+        .collect();
+    layer.iter().for_each(|fingerprint| {
+        gossip_layer_map.insert(fingerprint.clone(), 0);
+    });
+
+    let mut i: u8 = 0;
+    while !layer.is_empty() {
+        let Some(next) = i.checked_add(1) else {
+            warn!("Gossip stopped: too many layers");
+            break;
+        };
+        i = next;
+
+        let mut next_layer: HashSet<Arc<String>> = Default::default();
+        layer.iter().for_each(|fingerprint| {
+            let neighbours = match direction {
+                GossipDirection::In => [incoming.get(fingerprint), None],
+                GossipDirection::Out => [outgoing.get(fingerprint), None],
+                GossipDirection::Both => [incoming.get(fingerprint), outgoing.get(fingerprint)],
+            };
+            neighbours
+                .into_iter()
+                .flatten()
+                .flatten()
+                .for_each(|neighbour| {
+                    if !gossip_layer_map.contains_key(neighbour) {
+                        next_layer.insert(neighbour.clone());
+                        gossip_layer_map.insert(neighbour.clone(), i);
+                    }
+                });
+        });
+        debug!("Gossip layer {}: {} keys", i, next_layer.len());
+        layer = next_layer;
+    }
+
+    gossip_layer_map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{certify, generate, temp_path};
+
+    fn keyserver() -> KeyServer {
+        KeyServer::new("hkps://keys.example.org").unwrap()
+    }
+
+    #[test]
+    fn seeded_keys_do_not_count_against_max_keys() {
+        let bob = generate("bob <bob@example.org>");
+        let alice =
+            certify(generate("alice <alice@example.org>"), &bob).strip_secret_key_material();
+        let state_path = temp_path("gossip-max-keys");
+        std::fs::remove_file(&state_path).ok();
+        let keyserver = keyserver();
+        let limit = GossipLimit {
+            depth: 1,
+            max_keys: Some(0),
+            max_fanout_per_key: None,
+        };
+        let mut crawler = GossipCrawler::new(&keyserver, limit, &[alice.fingerprint()])
+            .with_state_file(&ClioPath::new(&state_path).unwrap())
+            .unwrap();
+        crawler.seed(alice.clone());
+        crawler.seed(bob.clone().strip_secret_key_material());
+        crawler.crawl();
+
+        // Both layers are gossiped without fetching anything
+        let state: GossipState =
+            serde_json::from_slice(&std::fs::read(&state_path).unwrap()).unwrap();
+        assert!(state.visited.contains(&alice.fingerprint().to_string()));
+        assert!(state.visited.contains(&bob.fingerprint().to_string()));
+        assert_eq!(state.fetched, BTreeMap::from([(0, 1), (1, 1)]));
+        std::fs::remove_file(&state_path).ok();
+        std::fs::remove_file(GossipCrawler::keyring_path(&state_path)).ok();
+    }
+
+    #[test]
+    fn state_of_other_roots_starts_over() {
+        let alice = generate("alice <alice@example.org>");
+        let state_path = temp_path("gossip-state");
+        let keyring_path = GossipCrawler::keyring_path(&state_path);
+        let state = GossipState {
+            roots: vec!["0000000000000000000000000000000000000000".to_string()],
+            layer: 1,
+            visited: HashSet::from(["0000000000000000000000000000000000000000".to_string()]),
+            ..Default::default()
+        };
+        std::fs::write(&state_path, serde_json::to_vec(&state).unwrap()).unwrap();
+        std::fs::write(&keyring_path, "").unwrap();
+
+        let keyserver = keyserver();
+        let crawler = GossipCrawler::new(&keyserver, Default::default(), &[alice.fingerprint()])
+            .with_state_file(&ClioPath::new(&state_path).unwrap())
+            .unwrap();
+        assert!(!crawler.is_resumed());
+        assert_eq!(crawler.state.roots, [alice.fingerprint().to_string()]);
+        assert!(!keyring_path.exists());
+        std::fs::remove_file(&state_path).ok();
+    }
+
+    #[test]
+    fn state_is_saved_at_the_end() {
+        let alice = generate("alice <alice@example.org>");
+        let state_path = temp_path("gossip-save");
+        std::fs::remove_file(&state_path).ok();
+
+        let keyserver = keyserver();
+        let mut crawler =
+            GossipCrawler::new(&keyserver, Default::default(), &[alice.fingerprint()])
+                .with_state_file(&ClioPath::new(&state_path).unwrap())
+                .unwrap();
+        crawler.seed(alice.clone().strip_secret_key_material());
+        crawler.crawl();
+
+        let state: GossipState =
+            serde_json::from_slice(&std::fs::read(&state_path).unwrap()).unwrap();
+        assert_eq!(state.roots, [alice.fingerprint().to_string()]);
+        assert!(state.visited.contains(&alice.fingerprint().to_string()));
+        assert!(state.frontier.is_empty());
+        std::fs::remove_file(&state_path).ok();
+    }
+}
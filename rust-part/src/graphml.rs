@@ -0,0 +1,105 @@
+use crate::cert::get_pgp_uid_by_node_uid;
+use crate::matrix::escape_html;
+use crate::structure::{GraphNodeUid, OpenPgpSig};
+use crate::{GOSSIP_LAYER_MAP, KEY_SET_MAP};
+use petgraph::graphmap::DiGraphMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// (id, domain, name, type) of every GraphML data key
+const KEYS: [(&str, &str, &str, &str); 14] = [
+    ("d0", "node", "fingerprint", "string"),
+    ("d1", "node", "uid", "string"),
+    ("d2", "node", "name", "string"),
+    ("d3", "node", "email", "string"),
+    ("d4", "node", "comment", "string"),
+    ("d5", "node", "is_revoked", "boolean"),
+    ("d6", "node", "is_primary", "boolean"),
+    ("d7", "node", "layer", "int"),
+    ("d8", "edge", "sig_type", "string"),
+    ("d9", "edge", "trust_level", "int"),
+    ("d10", "edge", "trust_value", "string"),
+    ("d11", "edge", "creation_time", "long"),
+    ("d12", "node", "algorithm", "string"),
+    ("d13", "node", "capabilities", "string"),
+];
+
+fn write_data(output: &mut String, key: &str, value: &str) {
+    writeln!(
+        output,
+        "      <data key=\"{}\">{}</data>",
+        key,
+        escape_html(value)
+    )
+    .unwrap();
+}
+
+/// Render the graph as GraphML, for Gephi, yEd, Cytoscape or networkx
+pub(crate) fn to_graphml(graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>) -> String {
+    let index: HashMap<GraphNodeUid, usize> =
+        graph.nodes().enumerate().map(|(i, v)| (v, i)).collect();
+    let mut output = String::new();
+    output += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    output += "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n";
+    KEYS.iter().for_each(|(id, domain, name, kind)| {
+        writeln!(
+            output,
+            "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>",
+            id, domain, name, kind
+        )
+        .unwrap();
+    });
+    output += "  <graph id=\"wot\" edgedefault=\"directed\">\n";
+
+    graph.nodes().enumerate().for_each(|(i, uid)| {
+        writeln!(output, "    <node id=\"n{}\">", i).unwrap();
+        write_data(&mut output, "d0", uid.fingerprint);
+        write_data(&mut output, "d1", uid.uid);
+        if let Some(pgp_uid) = get_pgp_uid_by_node_uid(&uid) {
+            write_data(&mut output, "d2", &pgp_uid.name);
+            write_data(&mut output, "d3", &pgp_uid.email);
+            write_data(&mut output, "d4", &pgp_uid.comment);
+            write_data(&mut output, "d5", &pgp_uid.is_revoked.to_string());
+            write_data(&mut output, "d6", &pgp_uid.is_primary.to_string());
+        }
+        if let Some(layer) = GOSSIP_LAYER_MAP
+            .get()
+            .and_then(|map| map.get(&uid.fingerprint.to_string()))
+        {
+            write_data(&mut output, "d7", &layer.to_string());
+        }
+        if let Some(pgp_key) = KEY_SET_MAP
+            .get()
+            .and_then(|map| map.get(&uid.fingerprint.to_string()))
+        {
+            write_data(&mut output, "d12", &pgp_key.primary_key.algorithm);
+            let capabilities: Vec<&str> = pgp_key
+                .usable_capabilities()
+                .iter()
+                .map(|v| v.name())
+                .collect();
+            write_data(&mut output, "d13", &capabilities.join(","));
+        }
+        output += "    </node>\n";
+    });
+
+    graph
+        .all_edges()
+        .enumerate()
+        .for_each(|(i, (issuer, target, sig))| {
+            writeln!(
+                output,
+                "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">",
+                i, index[&issuer], index[&target]
+            )
+            .unwrap();
+            write_data(&mut output, "d8", &format!("{:?}", sig.sig_type));
+            write_data(&mut output, "d9", &sig.trust_level.to_string());
+            write_data(&mut output, "d10", &format!("{:?}", sig.trust_value));
+            write_data(&mut output, "d11", &sig.creation_time.to_string());
+            output += "    </edge>\n";
+        });
+
+    output += "  </graph>\n</graphml>\n";
+    output
+}
@@ -0,0 +1,64 @@
+use crate::json::JsonGraph;
+use crate::structure::{GraphNodeUid, OpenPgpSig};
+use crate::style::Attrs;
+use crate::svg::to_svg;
+use petgraph::graphmap::DiGraphMap;
+
+const VIEWER_JS: &str = include_str!("viewer.js");
+
+const VIEWER_CSS: &str = "\
+body { margin: 0; display: flex; height: 100vh; font-family: sans-serif; font-size: small; }
+#sidebar { width: 320px; padding: 8px; overflow: auto; border-right: 1px solid #ccc; box-sizing: border-box; }
+#sidebar input[type=search] { width: 100%; box-sizing: border-box; }
+#sidebar fieldset { margin: 8px 0; }
+#sig-types label { display: block; }
+#details table { border-collapse: collapse; width: 100%; }
+#details th, #details td { border: 1px solid #ccc; padding: 2px 4px; text-align: left; word-break: break-all; }
+#details ul { padding-left: 16px; }
+#details .sig, #details .hint { color: #666; }
+#details .warning { color: #c00; }
+#details .unusable { text-decoration: line-through; }
+#canvas { flex: 1; overflow: hidden; }
+#canvas svg { width: 100%; height: 100%; cursor: grab; }
+#canvas .node { cursor: pointer; }
+#canvas .dimmed { opacity: 0.15; }
+#canvas .node.selected ellipse, #canvas .node.selected rect { stroke: blue; stroke-width: 3; }
+";
+
+/// Render a single self-contained HTML page with the SVG graph, its data and a small viewer to search,
+/// inspect and filter it
+pub(crate) fn to_html<'a, N, E>(
+    graph: &DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>,
+    node_attrs: N,
+    edge_attrs: E,
+) -> anyhow::Result<String>
+where
+    N: Fn(&GraphNodeUid) -> Attrs,
+    E: Fn(&GraphNodeUid, &GraphNodeUid, &OpenPgpSig) -> Attrs,
+{
+    let viewer_graph: JsonGraph = graph.into();
+    // Keep `</script>` in UIDs from closing the embedding script element
+    let data = serde_json::to_string(&viewer_graph)?.replace("</", "<\\/");
+    let svg = to_svg(graph, node_attrs, edge_attrs);
+    let svg = svg
+        .strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")
+        .unwrap_or(&svg);
+
+    let mut output = String::new();
+    output += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Web of Trust</title>\n<style>\n";
+    output += VIEWER_CSS;
+    output += "</style>\n</head>\n<body>\n<div id=\"sidebar\">\n";
+    output +=
+        "<input id=\"search\" type=\"search\" placeholder=\"Search name, email or fingerprint\">\n";
+    output += "<fieldset id=\"sig-types\"><legend>Signature types</legend></fieldset>\n";
+    output += "<fieldset id=\"layer-filter\"><legend>Gossip layer &le; <span id=\"layer-value\"></span></legend>";
+    output += "<input id=\"layer\" type=\"range\" min=\"0\" max=\"0\" step=\"1\"></fieldset>\n";
+    output += "<div id=\"details\"></div>\n</div>\n<div id=\"canvas\">\n";
+    output += svg;
+    output += "</div>\n<script type=\"application/json\" id=\"graph-data\">";
+    output += &data;
+    output += "</script>\n<script>\n";
+    output += VIEWER_JS;
+    output += "</script>\n</body>\n</html>\n";
+    Ok(output)
+}
@@ -0,0 +1,83 @@
+use crate::structure::{
+    GraphEdgeSig, GraphNodeUid, OpenPgpKeyCapability, OpenPgpKeyMaterial, OpenPgpSig, OpenPgpUid,
+};
+use crate::{GOSSIP_LAYER_MAP, KEY_SET_MAP};
+use petgraph::graphmap::DiGraphMap;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonNode<'a> {
+    pub(crate) fingerprint: &'a str,
+    pub(crate) label: String,
+    pub(crate) user_id: Option<&'a OpenPgpUid>,
+    pub(crate) key: Option<JsonKey<'a>>,
+    pub(crate) layer: Option<u8>,
+}
+
+/// The key of a node, with the capabilities of its keys which are neither expired nor revoked
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonKey<'a> {
+    pub(crate) capabilities: BTreeSet<OpenPgpKeyCapability>,
+    pub(crate) primary_key: &'a OpenPgpKeyMaterial,
+    pub(crate) subkeys: &'a [OpenPgpKeyMaterial],
+    /// Why the policy rejected the key, which is then shown as invalid
+    pub(crate) invalid_reason: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonEdge {
+    pub(crate) source: usize,
+    pub(crate) target: usize,
+    pub(crate) sig: GraphEdgeSig,
+}
+
+/// The graph as node and edge lists, edges refer to nodes by their index
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonGraph<'a> {
+    pub(crate) nodes: Vec<JsonNode<'a>>,
+    pub(crate) edges: Vec<JsonEdge>,
+}
+
+impl<'a> From<&DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>> for JsonGraph<'a> {
+    fn from(graph: &DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>) -> Self {
+        let index: HashMap<GraphNodeUid, usize> =
+            graph.nodes().enumerate().map(|(i, v)| (v, i)).collect();
+        JsonGraph {
+            nodes: graph
+                .nodes()
+                .map(|uid| {
+                    let pgp_key = KEY_SET_MAP
+                        .get()
+                        .and_then(|map| map.get(&uid.fingerprint.to_string()));
+                    let user_id = pgp_key.and_then(|v| v.uid(uid.uid));
+                    JsonNode {
+                        fingerprint: uid.fingerprint,
+                        label: user_id
+                            .map(|v| v.uid.to_string())
+                            .unwrap_or_else(|| uid.fingerprint.to_string()),
+                        user_id,
+                        key: pgp_key.map(|v| JsonKey {
+                            capabilities: v.usable_capabilities(),
+                            primary_key: &v.primary_key,
+                            subkeys: &v.subkeys,
+                            invalid_reason: v.invalid_reason.as_deref(),
+                        }),
+                        layer: GOSSIP_LAYER_MAP
+                            .get()
+                            .and_then(|map| map.get(&uid.fingerprint.to_string()))
+                            .copied(),
+                    }
+                })
+                .collect(),
+            edges: graph
+                .all_edges()
+                .map(|(issuer, target, sig)| JsonEdge {
+                    source: index[&issuer],
+                    target: index[&target],
+                    sig: (*sig).into(),
+                })
+                .collect(),
+        }
+    }
+}
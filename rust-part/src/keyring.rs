@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Context};
+use clio::ClioPath;
+use log::{debug, info, warn};
+use sequoia_openpgp::cert::CertParser;
+use sequoia_openpgp::parse::Parse;
+use sequoia_openpgp::{Cert, Fingerprint};
+use std::collections::HashMap;
+use std::io::Read;
+
+const KEYRING_EXTENSIONS: [&str; 3] = ["asc", "gpg", "pgp"];
+
+fn is_keyring_file(path: &ClioPath) -> bool {
+    path.extension()
+        .and_then(|v| v.to_str())
+        .map(|v| KEYRING_EXTENSIONS.contains(&v.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Expand the `--import` arguments into a list of keyring files, walking directories recursively
+pub(crate) fn expand_keyring_paths(paths: &[ClioPath]) -> anyhow::Result<Vec<ClioPath>> {
+    paths.iter().try_fold(Vec::new(), |mut files, path| {
+        if path.is_local() && !path.exists() {
+            return Err(anyhow!("Keyring not found: {}", path.display()));
+        }
+        if path.is_local() && path.is_dir() {
+            let found = path
+                .clone()
+                .files(is_keyring_file)
+                .with_context(|| format!("Failed to read directory: {}", path.display()))?;
+            if found.is_empty() {
+                warn!("{}: No keyring files found", path.display());
+            }
+            files.extend(found);
+        } else {
+            files.push(path.clone());
+        }
+        Ok(files)
+    })
+}
+
+/// Insert a cert into the map, merging it with a previously seen copy of the same key
+pub(crate) fn insert_cert(certs: &mut HashMap<Fingerprint, Cert>, cert: Cert) {
+    let fingerprint = cert.fingerprint();
+    match certs.remove(&fingerprint) {
+        None => {
+            certs.insert(fingerprint, cert);
+        }
+        Some(exist) => {
+            let merged = exist.clone().merge_public(cert).unwrap_or_else(|e| {
+                warn!("Failed to merge key {}: {}", fingerprint, e);
+                exist
+            });
+            certs.insert(fingerprint, merged);
+        }
+    }
+}
+
+/// Parse every cert found in a single keyring file, binary or ASCII-armored
+pub(crate) fn read_keyring_file(
+    path: &ClioPath,
+    certs: &mut HashMap<Fingerprint, Cert>,
+) -> anyhow::Result<()> {
+    let name = path.display();
+    let mut keyring: Vec<u8> = Default::default();
+    path.clone()
+        .open()
+        .with_context(|| format!("Failed to open keyring: {}", name))?
+        .read_to_end(&mut keyring)
+        .with_context(|| format!("Failed to read keyring: {}", name))?;
+
+    let mut valid: usize = 0;
+    let mut invalid: usize = 0;
+    CertParser::from_bytes(&keyring)
+        .with_context(|| format!("Failed to parse keyring: {}", name))?
+        .for_each(|r| match r {
+            Ok(cert) => {
+                valid += 1;
+                insert_cert(certs, cert);
+            }
+            Err(e) => {
+                invalid += 1;
+                warn!("{}: Invalid Cert: {}", name, e);
+            }
+        });
+
+    if invalid > 0 {
+        warn!("{}: {} certs loaded, {} invalid", name, valid, invalid);
+    } else {
+        info!("{}: {} certs loaded", name, valid);
+    }
+    Ok(())
+}
+
+/// Load certs from every `--import` source, reporting failures per file
+pub(crate) fn read_keyrings(paths: &[ClioPath]) -> anyhow::Result<HashMap<Fingerprint, Cert>> {
+    let mut certs: HashMap<Fingerprint, Cert> = Default::default();
+    let files = expand_keyring_paths(paths)?;
+    debug!("Keyring files: {:?}", files);
+    files.iter().for_each(|path| {
+        read_keyring_file(path, &mut certs)
+            .err()
+            .inspect(|e| warn!("{:#}", e));
+    });
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    #[test]
+    fn expand_directory_recursively() {
+        let dir = temp_dir("expand");
+        create_dir_all(dir.join("sub")).unwrap();
+        write(dir.join("a.asc"), "").unwrap();
+        write(dir.join("sub").join("b.GPG"), "").unwrap();
+        write(dir.join("sub").join("c.pgp"), "").unwrap();
+        write(dir.join("notes.txt"), "").unwrap();
+
+        let mut files: Vec<String> = expand_keyring_paths(&[ClioPath::new(&dir).unwrap()])
+            .unwrap()
+            .iter()
+            .map(|v| v.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["a.asc", "b.GPG", "c.pgp"]);
+        remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn expand_keeps_files_and_stdin() {
+        let dir = temp_dir("files");
+        let file = dir.join("keyring");
+        write(&file, "").unwrap();
+
+        let files =
+            expand_keyring_paths(&[ClioPath::new(&file).unwrap(), ClioPath::new("-").unwrap()])
+                .unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[1].is_std());
+        remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn expand_missing_path() {
+        let dir = temp_dir("missing");
+        let missing = ClioPath::new(dir.join("missing.gpg")).unwrap();
+        assert!(expand_keyring_paths(&[missing]).is_err());
+        remove_dir_all(&dir).ok();
+    }
+}
@@ -1,42 +1,79 @@
-use crate::cert::get_pgp_uid_by_node_uid;
-use crate::cli::{Cli, Commands, GenCommand};
-use crate::structure::{GraphNodeUid, OpenPgpKey, OpenPgpSig, OpenPgpUid, SigType};
-use anyhow::anyhow;
-use clap::{CommandFactory, Parser};
-use log::{debug, error, trace, warn};
+use crate::audit::{AuditEntry, AuditIssue};
+use crate::cli::{Cli, Commands, GenCommand, GossipDirection, OutputFormat};
+use crate::gossip::{GossipCrawler, GossipLimit};
+use crate::structure::{GraphNodeUid, OpenPgpKey, OpenPgpSig, OpenPgpUid, OpenPgpValidity};
+use anyhow::{anyhow, Context};
+use clap::{CommandFactory, FromArgMatches};
+use log::{debug, error, info, trace, warn};
 use petgraph::dot::Dot;
 use petgraph::graphmap::DiGraphMap;
 use sequoia_net::KeyServer;
-use sequoia_openpgp::cert::CertParser;
-use sequoia_openpgp::parse::Parse;
-use sequoia_openpgp::policy::StandardPolicy;
 use sequoia_openpgp::{Cert, Fingerprint};
-use sequoia_wot::{CertSynopsis, RevocationStatus, UserIDSynopsis};
 use std::collections::{HashMap, HashSet};
 use std::default::Default;
 use std::fs::create_dir_all;
-use std::io::{Error, Read};
 use std::process::exit;
 use std::sync::{Arc, OnceLock};
-use std::time::{SystemTime, UNIX_EPOCH};
 
+mod analyze;
+mod audit;
+mod ca;
 mod cert;
+mod certstore;
 mod cli;
+mod config;
+mod csv;
+mod diff;
+mod gnupg;
+mod gossip;
+mod graphml;
+mod html;
+mod json;
+mod keyring;
+mod matrix;
+mod mermaid;
+mod plantuml;
 mod structure;
+mod style;
+mod suggest;
+mod svg;
+#[cfg(test)]
+mod test_util;
 
 static CLI_ARGS: OnceLock<Cli> = OnceLock::new();
 static KEY_SET_MAP: OnceLock<HashMap<Arc<String>, OpenPgpKey>> = OnceLock::new();
 static GOSSIP_LAYER_MAP: OnceLock<HashMap<Arc<String>, u8>> = OnceLock::new();
+static OWNER_TRUST_MAP: OnceLock<HashMap<Arc<String>, OpenPgpValidity>> = OnceLock::new();
+static AUDIT_MAP: OnceLock<HashMap<Arc<String>, AuditEntry>> = OnceLock::new();
+static THEME: OnceLock<style::Theme> = OnceLock::new();
+static ORGANIZATIONS: OnceLock<Vec<ca::Organization>> = OnceLock::new();
 
 #[tokio::main]
 async fn main() {
-    let args = Cli::parse();
-    let policy = StandardPolicy::new();
+    let matches = Cli::command().get_matches();
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     let log_level = args.verbose.log_level_filter();
     env_logger::Builder::new().filter_level(log_level).init();
+    (|| -> anyhow::Result<()> {
+        if let Some((path, config)) = config::read_config(args.config.as_ref())? {
+            config
+                .apply(&path, &mut args, &matches)
+                .with_context(|| format!("Invalid config: {}", path.display()))?;
+        }
+        if let Some(path) = &args.style {
+            THEME.set(style::read_theme(path)?).unwrap();
+        }
+        args.check_output_format()
+    })()
+    .err()
+    .inspect(|e| {
+        error!("{:#}", e);
+        exit(1);
+    });
+    let policy = cert::policy(args.policy, args.accept_sha1_until);
     debug!("Cli args: {:?}", args);
 
-    if let Some(command) = args.command {
+    if let Some(command) = args.command.clone() {
         match command {
             Commands::Gen { gen_command } => {
                 (|| -> anyhow::Result<()> {
@@ -48,9 +85,16 @@ async fn main() {
                             create_dir_all(&out_dir)?;
                             clap_mangen::generate_to(Cli::command(), out_dir)?;
                         }
-                        GenCommand::Complete { args, mut output } => {
+                        GenCommand::Complete { args: shell } => {
                             let name = cmd.get_display_name().unwrap_or_else(|| cmd.get_name());
-                            clap_complete::generate(args, &mut Cli::command(), name, &mut output);
+                            let mut complete: Vec<u8> = Default::default();
+                            clap_complete::generate(
+                                shell,
+                                &mut Cli::command(),
+                                name,
+                                &mut complete,
+                            );
+                            args.write_output(&String::from_utf8(complete)?)?;
                         }
                     }
                     Ok(())
@@ -62,6 +106,50 @@ async fn main() {
                 });
                 exit(0);
             }
+            Commands::Diff {
+                old,
+                new,
+                json,
+                dot,
+            } => {
+                CLI_ARGS.set(args.clone()).unwrap();
+                (|| -> anyhow::Result<()> {
+                    args.check_output_format()?;
+                    let format = args.explicit_output_format();
+                    if let Some(format) =
+                        format.filter(|v| ![OutputFormat::Json, OutputFormat::Dot].contains(v))
+                    {
+                        return Err(anyhow!(
+                            "The diff can't be written as {:?}, only text, JSON or DOT",
+                            format
+                        ));
+                    }
+                    let old = diff::read_key_set(&old, &policy)?;
+                    let new = diff::read_key_set(&new, &policy)?;
+                    let keyring_diff = diff::diff(&old, &new, args.show_self_sigs);
+                    let content = if json || format == Some(OutputFormat::Json) {
+                        format!("{}\n", serde_json::to_string_pretty(&keyring_diff)?)
+                    } else if dot || format == Some(OutputFormat::Dot) {
+                        format!(
+                            "{}\n",
+                            diff::diff_dot(&old, &new, &keyring_diff, args.show_self_sigs)
+                        )
+                    } else {
+                        keyring_diff.to_string()
+                    };
+                    args.write_output(&content)
+                })()
+                .err()
+                .inspect(|e| {
+                    error!("{:#}", e);
+                    exit(1);
+                });
+                exit(0);
+            }
+            Commands::Audit { .. }
+            | Commands::Analyze { .. }
+            | Commands::Suggest { .. }
+            | Commands::Matrix => {}
         }
     }
 
@@ -75,48 +163,54 @@ async fn main() {
             return Err(anyhow!("Online mode is not allowed with depth limit 0"));
         }
 
-        if args.import.is_none() && args.fingerprint.is_none() {
+        let args_keyring_is_none = args.import.is_none() && args.gpg_homedir.is_none() && args.cert_store.is_none();
+
+        if args_keyring_is_none && args.fingerprint.is_none() {
             return Err(anyhow!("No input found, please consider provide at least one of keyring or fingerprint."));
         }
 
-        if args.fingerprint.is_some() && args.import.is_none() && !args.online {
+        if args.fingerprint.is_some() && args_keyring_is_none && !args.online {
             return Err(anyhow!("Offline mode is not allowed without keyring"));
         }
 
         let mut fingerprints: HashSet<Fingerprint> = Default::default();
         let mut certs: HashMap<Fingerprint, Cert> = Default::default();
 
-        let args_import_is_none = args.import.is_none();
-
-        args.import.map_or(Ok(()),
-                           |mut input| {
-                               let mut keyring: Vec<u8> = Default::default();
-                               input.read_to_end(&mut keyring)?;
-                               CertParser::from_bytes(&keyring).map_or_else(
-                                   |e| warn!("{}" ,e),
-                                   |v| {
-                                       v.for_each(|r| {
-                                           r.map_or_else(
-                                               |e| {
-                                                   warn!("Invalid Cert: {}", e);
-                                               },
-                                               |v| {
-                                                   if args.online {
-                                                       fingerprints.insert(v.fingerprint());
-                                                   }
-                                                   certs.insert(v.fingerprint(), v);
-                                               },
-                                           )
-                                       });
-                                   },
-                               );
-                               Ok(())
-                           },
-        ).err().inspect(|e: &Error| {
-            warn!("{}", e);
-        });
+        if let Some(import) = &args.import {
+            keyring::read_keyrings(import)?.into_iter().for_each(|(fingerprint, cert)| {
+                if args.online {
+                    fingerprints.insert(fingerprint.clone());
+                }
+                certs.insert(fingerprint, cert);
+            });
+        }
+
+        if let Some(homedir) = &args.gpg_homedir {
+            gnupg::read_gnupg_home(homedir)?.into_iter().for_each(|(fingerprint, cert)| {
+                if args.online {
+                    fingerprints.insert(fingerprint.clone());
+                }
+                keyring::insert_cert(&mut certs, cert);
+            });
+            if args.gpg_ownertrust {
+                OWNER_TRUST_MAP.set(gnupg::read_ownertrust(homedir)?).unwrap();
+            }
+        }
+
+        let mut trust_root: Option<Fingerprint> = None;
+
+        if let Some(cert_store) = &args.cert_store {
+            let (cert_store_certs, cert_store_trust_root) = certstore::read_cert_store(cert_store)?;
+            cert_store_certs.into_iter().for_each(|(fingerprint, cert)| {
+                if args.online {
+                    fingerprints.insert(fingerprint.clone());
+                }
+                keyring::insert_cert(&mut certs, cert);
+            });
+            trust_root = cert_store_trust_root;
+        }
 
-        let args_fingerprints: Vec<Fingerprint> = args.fingerprint.map_or(Default::default(), |v| {
+        let mut args_fingerprints: Vec<Fingerprint> = args.fingerprint.clone().map_or(Default::default(), |v| {
             v.into_iter().filter_map(|v| {
                 Fingerprint::from_hex(v.as_str()).map_or_else(|e| {
                     warn!("Invalid Fingerprint: {}", e);
@@ -127,6 +221,25 @@ async fn main() {
             }).collect()
         });
 
+        if args.gossip.is_some() {
+            if let Some(trust_root) = &trust_root {
+                if !args_fingerprints.contains(trust_root) {
+                    args_fingerprints.push(trust_root.clone());
+                }
+            }
+        }
+
+        // Every pair of attendees is a candidate, so they have to be given rather than all keys
+        let suggest_root: Option<Fingerprint> = match &args.command {
+            Some(Commands::Suggest { root, .. }) => {
+                if args_fingerprints.is_empty() {
+                    return Err(anyhow!("Suggest needs the attendees given by --fingerprint"));
+                }
+                root.as_deref().map(Fingerprint::from_hex).transpose()?
+            }
+            _ => None,
+        };
+
         fingerprints.extend(args_fingerprints.iter().cloned());
 
         if args.online {
@@ -140,164 +253,79 @@ async fn main() {
 
         if args.online && !args_fingerprints.is_empty() && args.gossip.is_some() {
             let gossip = args.gossip.unwrap_or(0);
-            if gossip > 0 {
-                let mut result: HashMap<Fingerprint, Cert> = Default::default();
-                cert::fetch_cert_from_keyserver_once_lock_recursive(&keyserver, &args_fingerprints.iter().cloned().collect(), gossip, &mut result);
-                result.into_iter()
-                    .for_each(|(fingerprint, cert)| {
-                        certs.insert(fingerprint, cert);
+            if args.gossip_direction == GossipDirection::Out {
+                info!("Outgoing certifications can't be looked up on keyserver, gossiping over loaded keys only");
+            } else if let (true, Some(keyserver)) = (gossip > 0, keyserver.get()) {
+                let mut crawler = GossipCrawler::new(keyserver, GossipLimit {
+                    depth: gossip,
+                    max_keys: args.max_keys,
+                    max_fanout_per_key: args.max_fanout_per_key,
+                }, &args_fingerprints);
+                if let Some(gossip_state) = &args.gossip_state {
+                    crawler = crawler.with_state_file(gossip_state)?;
+                }
+                fingerprints.iter()
+                    .filter_map(|fingerprint| certs.get(fingerprint))
+                    .for_each(|cert| crawler.seed(cert.clone()));
+                crawler.crawl().into_iter()
+                    .for_each(|(_, cert)| {
+                        keyring::insert_cert(&mut certs, cert);
                     });
             }
         }
 
         trace!("{:?}", certs);
 
+        let audit_enabled = args.audit || matches!(args.command, Some(Commands::Audit { .. }));
+        let mut audit: HashMap<Arc<String>, AuditEntry> = Default::default();
+
         let mut key_set: HashMap<Arc<String>, OpenPgpKey> = certs
             .iter()
             .filter(|(fingerprint, _)| {
-                if args.gossip.is_none() && !args_import_is_none && !args_fingerprints.is_empty() {
-                    args_fingerprints.contains(fingerprint)
+                if args.gossip.is_none() && !args_keyring_is_none && !args_fingerprints.is_empty() {
+                    args_fingerprints.contains(fingerprint) || suggest_root.as_ref() == Some(fingerprint)
                 } else {
                     true
                 }
             })
-            .filter_map(|(_, cert)| {
-                cert.with_policy(&policy, SystemTime::now())
-                    .map_err(|e| error!("{}", e))
-                    .map_or_else(
-                        |_| None,
-                        |cert| {
-                            let cert_synopsis: CertSynopsis = cert.clone().into();
-                            let id = Arc::new(cert_synopsis.fingerprint().to_string());
-                            let primary_id = Arc::new(cert.primary_userid().map(|v| v.userid().to_string()).unwrap_or_default());
-                            Some((
-                                id.clone(),
-                                OpenPgpKey {
-                                    id: id.clone(),
-                                    is_revoked: cert_synopsis.revocation_status()
-                                        != RevocationStatus::NotAsFarAsWeKnow,
-                                    is_expired: cert_synopsis
-                                        .expiration_time()
-                                        .map_or_else(|| false, |v| v < SystemTime::now()),
-                                    user_ids: cert
-                                        .userids()
-                                        .map(|user_id| {
-                                            let user_id_synopsis: UserIDSynopsis =
-                                                user_id.clone().into();
-                                            let uid = Arc::new(user_id.to_string());
-                                            (uid.clone(), OpenPgpUid {
-                                                fingerprint: id.clone(),
-                                                uid: uid.clone(),
-                                                name: user_id.name2().map_or_else(
-                                                    |e| {
-                                                        warn!("Invalid Name: {}", e);
-                                                        "".to_string()
-                                                    },
-                                                    |v| {
-                                                        v.map_or_else(
-                                                            || "".to_string(),
-                                                            |v| v.to_string(),
-                                                        )
-                                                    },
-                                                ),
-                                                email: user_id.email2().map_or_else(
-                                                    |e| {
-                                                        warn!("Invalid Email: {}", e);
-                                                        "".to_string()
-                                                    },
-                                                    |v| {
-                                                        v.map_or_else(
-                                                            || "".to_string(),
-                                                            |v| v.to_string(),
-                                                        )
-                                                    },
-                                                ),
-                                                comment: user_id.comment2().map_or_else(
-                                                    |e| {
-                                                        warn!("Invalid Comment: {}", e);
-                                                        "".to_string()
-                                                    },
-                                                    |v| {
-                                                        v.map_or_else(
-                                                            || "".to_string(),
-                                                            |v| v.to_string(),
-                                                        )
-                                                    },
-                                                ),
-                                                sig_vec: user_id
-                                                    .signatures()
-                                                    .filter_map(|sig| {
-                                                        Some(OpenPgpSig {
-                                                            fingerprint: sig.issuer_fingerprints().next().map_or_else(|| {
-                                                                warn!("Invalid Issuer: {:?}", sig);
-                                                                "".to_string()
-                                                            }, |v| v.to_string()),
-                                                            uid: sig.signers_user_id().map_or_else(|| {
-                                                                "".to_string()
-                                                            }, |v| String::from_utf8(Vec::from(v)).unwrap_or_else(|e| {
-                                                                warn!("Invalid Signer User ID: {}", e);
-                                                                "".to_string()
-                                                            })),
-                                                            trust_level: sig.trust_signature().unwrap_or((0, 0)).0,
-                                                            trust_value: sig.trust_signature().unwrap_or((0, 0)).1.into(),
-                                                            sig_type: sig.typ().into(),
-                                                            creation_time: sig.signature_creation_time()?.duration_since(UNIX_EPOCH).ok()?.as_secs(),
-                                                        })
-                                                    })
-                                                    .collect(),
-                                                is_revoked: user_id_synopsis.revocation_status()
-                                                    != RevocationStatus::NotAsFarAsWeKnow,
-                                                is_primary: user_id.userid().to_string() == *primary_id,
-                                            })
-                                        })
-                                        .collect(),
-                                    primary_user_id: primary_id.clone(),
-                                },
-                            ))
-                        },
-                    )
+            .filter_map(|(fingerprint, cert)| match cert::validate_cert(cert, &policy) {
+                Err(e) => {
+                    error!("{}: {}", fingerprint, e);
+                    None
+                }
+                Ok((cert, Some(e))) => {
+                    warn!("{}: rejected by policy, shown as invalid: {}", fingerprint, e);
+                    let mut pgp_key = cert::openpgp_key(&cert);
+                    if audit_enabled {
+                        audit.insert(pgp_key.id.clone(), AuditEntry {
+                            fingerprint: pgp_key.id.to_string(),
+                            uid: cert.userids().next().map(|v| v.userid().to_string()).unwrap_or_default(),
+                            issues: vec![AuditIssue::RejectedByPolicy { reason: e.to_string() }],
+                        });
+                    }
+                    pgp_key.invalid_reason = Some(e.to_string());
+                    Some((pgp_key.id.clone(), pgp_key))
+                }
+                Ok((cert, None)) => {
+                    let pgp_key = cert::openpgp_key(&cert);
+                    if audit_enabled {
+                        audit.insert(pgp_key.id.clone(), AuditEntry {
+                            fingerprint: pgp_key.id.to_string(),
+                            uid: pgp_key.primary_user_id.to_string(),
+                            issues: audit::audit_cert(&cert, args.warn_expiry),
+                        });
+                    }
+                    Some((pgp_key.id.clone(), pgp_key))
+                }
             })
             .collect();
 
-        if args.gossip.is_some() {
-            let mut gossip_layers: HashMap<u8, HashSet<Arc<String>>> = Default::default();
-            let mut gossip_layer_map: HashMap<Arc<String>, u8> = Default::default();
-
-            let mut layer: HashSet<Arc<String>> = Default::default();
-            args_fingerprints.iter().for_each(|fingerprint| {
-                let fingerprint: Arc<String> = fingerprint.to_string().into();
-                layer.insert(fingerprint.clone());
-                gossip_layer_map.insert(fingerprint, 0);
-            });
-            gossip_layers.insert(0, layer);
-
-            let mut i: u8 = 0;
-
-            while let Some(last_layer) = gossip_layers.get(&i) {
-                if last_layer.is_empty() {
-                    break;
-                }
-                i += 1;
-
-                let mut layer: HashSet<Arc<String>> = Default::default();
-                last_layer.iter().for_each(|fingerprint| {
-                    key_set.get(fingerprint).inspect(|cert| {
-                        cert.user_ids.iter().for_each(|(_, pgp_uid)| {
-                            pgp_uid.sig_vec.iter().for_each(|sig| {
-                                if !gossip_layer_map.contains_key(&sig.fingerprint) {
-                                    layer.insert(sig.fingerprint.clone().into());
-                                    gossip_layer_map.insert(sig.fingerprint.clone().into(), i);
-                                }
-                            })
-                        })
-                    });
-                });
+        if audit_enabled {
+            AUDIT_MAP.set(audit).unwrap();
+        }
 
-                if layer.is_empty() {
-                    break;
-                }
-                gossip_layers.insert(i, layer);
-            }
+        if args.gossip.is_some() {
+            let gossip_layer_map = gossip::gossip_layers(&key_set, &args_fingerprints, args.gossip_direction);
 
             key_set = key_set.into_iter().filter_map(|(fingerprint, pgp_key)| {
                 if let Some(layer) = gossip_layer_map.get(&fingerprint) {
@@ -317,6 +345,23 @@ async fn main() {
 
         KEY_SET_MAP.set(key_set.clone()).unwrap();
 
+        if args.openpgp_ca {
+            let organizations = ca::detect_organizations(&key_set);
+            organizations.iter().for_each(|v| {
+                info!("{}: CA of {} with {} members", v.ca, v.domain, v.members.len());
+            });
+            ORGANIZATIONS.set(organizations).unwrap();
+        }
+
+        if let Some(directory) = &args.extract_photos {
+            cert::extract_photos(&key_set, directory)?;
+        }
+
+        if args.list_unknown_issuers {
+            let content: String = cert::unknown_issuers(&key_set).iter().map(|v| format!("{}\n", v)).collect();
+            return args.write_output(&content);
+        }
+
         debug!(
             "{}",
             serde_json::to_string(&key_set).unwrap_or_else(|e| e.to_string())
@@ -324,55 +369,146 @@ async fn main() {
 
         let mut graph: DiGraphMap<GraphNodeUid, &OpenPgpSig> = DiGraphMap::new();
 
+        // Nodes and edges are inserted in sorted order, so identical inputs produce identical output
+        let mut nodes: Vec<GraphNodeUid> = Default::default();
+        let mut edges: Vec<(GraphNodeUid, GraphNodeUid, &OpenPgpSig)> = Default::default();
+
+        // UIDs shown as nodes, and user attributes if asked for
+        fn shown_uids<'k>(
+            pgp_key: &'k OpenPgpKey,
+            args: &'k Cli,
+        ) -> impl Iterator<Item = &'k OpenPgpUid> {
+            pgp_key
+                .user_ids
+                .values()
+                .filter(|pgp_uid| pgp_uid.is_primary || !args.show_primary_uid_only)
+                .chain(
+                    pgp_key
+                        .user_attributes
+                        .values()
+                        .filter(|_| args.show_user_attributes),
+                )
+        }
+
         key_set.iter().for_each(|(_, pgp_key)| {
-            pgp_key.user_ids.iter().for_each(|(_, pgp_uid)| {
-                if !pgp_uid.is_primary && args.show_primary_uid_only {
-                    return;
-                }
-                graph.add_node(pgp_uid.into());
+            shown_uids(pgp_key, &args).for_each(|pgp_uid| {
+                nodes.push(pgp_uid.into());
             });
         });
 
         key_set.iter().for_each(|(_, pgp_key)| {
-            pgp_key.user_ids.iter().for_each(|(_, pgp_uid)| {
-                if !pgp_uid.is_primary && args.show_primary_uid_only {
-                    return;
-                }
+            shown_uids(pgp_key, &args).for_each(|pgp_uid| {
                 pgp_uid.sig_vec.iter().for_each(|sig| {
                     key_set.get(&sig.fingerprint).inspect(|key_id| {
                         key_id.user_ids.get(&key_id.primary_user_id).inspect(|sig_uid| {
                             if !args.show_self_sigs && sig_uid.uid == pgp_uid.uid {
                                 return;
                             }
-                            graph.add_edge(sig_uid.into(), pgp_uid.into(), sig);
+                            edges.push((sig_uid.into(), pgp_uid.into(), sig));
                         });
                     });
+                    if args.show_unknown_issuers && !sig.fingerprint.is_empty() && !key_set.contains_key(&sig.fingerprint) {
+                        let issuer = GraphNodeUid { fingerprint: sig.fingerprint.as_str(), uid: "" };
+                        edges.push((issuer, pgp_uid.into(), sig));
+                    }
                 });
             })
         });
 
-        let binding = &|_, (_, uid)| {
-            let mut attr = get_pgp_uid_by_node_uid(uid).map(|v| {
-                if v.is_revoked { " color = red " } else { "" }
-            }).unwrap_or("").to_string();
-            if args.gossip.is_some() {
-                if let Some(map) = GOSSIP_LAYER_MAP.get() {
-                    if let Some(layer) = map.get(&uid.fingerprint.to_string()) {
-                        if *layer == 0 {
-                            attr += " root = true ";
-                        }
-                    }
-                }
+        // The newest signature between two UIDs is inserted last and wins
+        edges.sort_by_key(|(issuer, target, sig)| (*issuer, *target, sig.creation_time, sig.sig_type));
+        if args.introducers_only {
+            // A trust signature superseded by a newer certification or revocation doesn't make an introducer
+            edges.reverse();
+            edges.dedup_by_key(|(issuer, target, _)| (*issuer, *target));
+            edges.reverse();
+            edges.retain(|(_, _, sig)| sig.trust_level > 0);
+            nodes.retain(|node| edges.iter().any(|(issuer, target, _)| issuer == node || target == node));
+        }
+
+        nodes.sort();
+        nodes.into_iter().for_each(|node| {
+            graph.add_node(node);
+        });
+        edges.into_iter().for_each(|(issuer, target, sig)| {
+            graph.add_edge(issuer, target, sig);
+        });
+
+        if let Some(Commands::Analyze { json, top }) = &args.command {
+            let mut report = analyze::analyze(&graph, &key_set);
+            if let Some(top) = top {
+                report.ranking.truncate(*top);
             }
-            attr
-        };
+            return if *json || args.explicit_output_format() == Some(OutputFormat::Json) {
+                args.write_output(&format!("{}\n", serde_json::to_string_pretty(&report)?))
+            } else {
+                args.write_output(&report.to_string())
+            };
+        }
 
-        let dot = Dot::with_attr_getters(&graph, &[], &|_, (_, _, sig)|
-            (if sig.sig_type == SigType::Revoke { " color = red " } else { "" }).to_string(), binding);
-        let content = format!("{}", dot);
-        println!("{}", content);
+        if let Some(Commands::Suggest { json, top, .. }) = &args.command {
+            let root = suggest_root.map(|v| v.to_string());
+            let attendees: Vec<String> = args_fingerprints.iter().map(|v| v.to_string()).collect();
+            let mut report = suggest::suggest(&graph, &key_set, &attendees, root.as_deref())?;
+            report.suggestions.truncate(*top);
+            return if *json || args.explicit_output_format() == Some(OutputFormat::Json) {
+                args.write_output(&format!("{}\n", serde_json::to_string_pretty(&report)?))
+            } else {
+                args.write_output(&report.to_string())
+            };
+        }
 
-        Ok(())
+        if let Some(Commands::Audit { json }) = &args.command {
+            let summary = AUDIT_MAP.get().map(audit::audit_summary).unwrap_or_default();
+            return if *json || args.explicit_output_format() == Some(OutputFormat::Json) {
+                args.write_output(&format!("{}\n", serde_json::to_string_pretty(&summary)?))
+            } else {
+                args.write_output(&summary.iter().map(|entry| entry.to_string()).collect::<String>())
+            };
+        }
+
+        if let Some(Commands::Matrix) = &args.command {
+            let attendees: Vec<String> = args_fingerprints.iter().map(|v| v.to_string()).collect();
+            let matrix = matrix::matrix(&key_set, &attendees, args.show_primary_uid_only);
+            return match args.explicit_output_format() {
+                None => args.write_output(&matrix.to_text()),
+                Some(OutputFormat::Csv) => args.write_output(&matrix.to_csv()),
+                Some(OutputFormat::Html) => args.write_output(&matrix.to_html()),
+                Some(format) => Err(anyhow!("The matrix can't be written as {:?}, only text, CSV or HTML", format)),
+            };
+        }
+
+        let trust_root = trust_root.map(|v| v.to_string());
+
+        let binding = &|_, (_, uid): (_, &GraphNodeUid)| style::node_attrs(uid, trust_root.as_deref()).to_string();
+        let edge_binding = &|_, (issuer, target, sig): (GraphNodeUid, GraphNodeUid, &&OpenPgpSig)| style::edge_attrs(&issuer, &target, sig).to_string();
+
+        let output_format = args.output_format();
+        if matches!(output_format, OutputFormat::Csv | OutputFormat::Tsv) && !args.edges_only {
+            match &args.output {
+                Some(directory) if args.output_is_table_directory() => {
+                    return csv::write_directory(&graph, directory, output_format);
+                }
+                Some(_) => {}
+                None => return Err(anyhow!("Nodes and edges need an output directory given by `--output`, or use `--edges-only`")),
+            }
+        }
+
+        let content = match output_format {
+            OutputFormat::Dot => {
+                let dot = Dot::with_attr_getters(&graph, &[], edge_binding, binding).to_string();
+                let body = dot.strip_suffix("}\n").unwrap_or(&dot);
+                format!("{}{}{}}}\n\n", body, style::layer_ranks(&graph), ca::organization_clusters(&graph))
+            }
+            OutputFormat::Svg => svg::to_svg(&graph, |uid| style::node_attrs(uid, trust_root.as_deref()), style::edge_attrs),
+            OutputFormat::Html => html::to_html(&graph, |uid| style::node_attrs(uid, trust_root.as_deref()), style::edge_attrs)?,
+            OutputFormat::Mermaid => mermaid::to_mermaid(&graph, |uid| style::node_attrs(uid, trust_root.as_deref()), style::edge_attrs),
+            OutputFormat::Plantuml => plantuml::to_plantuml(&graph, |uid| style::node_attrs(uid, trust_root.as_deref()), style::edge_attrs),
+            OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(&json::JsonGraph::from(&graph))?),
+            OutputFormat::Graphml => graphml::to_graphml(&graph),
+            OutputFormat::Csv | OutputFormat::Tsv => csv::edges(&graph, output_format),
+        };
+        args.write_output(&content)
     })()
         .map_or_else(
             |e| -> i32 {
@@ -0,0 +1,360 @@
+use crate::csv::write_row;
+use crate::structure::{OpenPgpKey, OpenPgpSig, SigType};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) enum MatrixCell {
+    OwnKey,
+    Missing,
+    Certified {
+        sig_type: SigType,
+        creation_time: u64,
+    },
+    Revoked {
+        creation_time: u64,
+    },
+}
+
+impl MatrixCell {
+    fn class(&self) -> &'static str {
+        match self {
+            MatrixCell::OwnKey => "own",
+            MatrixCell::Missing => "missing",
+            MatrixCell::Certified { .. } => "certified",
+            MatrixCell::Revoked { .. } => "revoked",
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            MatrixCell::OwnKey => "self".to_string(),
+            MatrixCell::Missing => "-".to_string(),
+            MatrixCell::Certified {
+                sig_type,
+                creation_time,
+            } => format!("{:?} {}", sig_type, format_date(*creation_time)),
+            MatrixCell::Revoked { creation_time } => {
+                format!("Revoked {}", format_date(*creation_time))
+            }
+        }
+    }
+}
+
+/// Format a UNIX timestamp as an ISO 8601 date in UTC
+pub(crate) fn format_date(timestamp: u64) -> String {
+    // Howard Hinnant's civil_from_days
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_prime = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_prime + 2) / 5 + 1;
+    let month = if month_prime < 10 {
+        month_prime + 3
+    } else {
+        month_prime - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Matrix {
+    pub(crate) keys: Vec<(String, String)>,
+    pub(crate) cells: Vec<Vec<MatrixCell>>,
+}
+
+/// Build the matrix of the newest certification of each issuer (row) on each target (column)
+pub(crate) fn matrix(
+    key_set: &HashMap<Arc<String>, OpenPgpKey>,
+    attendees: &[String],
+    primary_uid_only: bool,
+) -> Matrix {
+    let mut keys: Vec<&OpenPgpKey> = key_set
+        .values()
+        .filter(|v| attendees.is_empty() || attendees.contains(&v.id))
+        .collect();
+    keys.sort_by(|a, b| {
+        a.primary_user_id
+            .cmp(&b.primary_user_id)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let cells = keys
+        .iter()
+        .map(|issuer| {
+            keys.iter()
+                .map(|target| {
+                    if issuer.id == target.id {
+                        return MatrixCell::OwnKey;
+                    }
+                    // The newest signature on each UID, a live certification of any UID wins over
+                    // the revocations of the others
+                    let newest: Vec<&OpenPgpSig> = target
+                        .user_ids
+                        .values()
+                        .filter(|uid| uid.is_primary || !primary_uid_only)
+                        .filter_map(|uid| {
+                            uid.sig_vec
+                                .iter()
+                                .filter(|sig| sig.is_issued_by(&issuer.id))
+                                .max_by_key(|sig| sig.creation_time)
+                        })
+                        .collect();
+                    let certified = newest
+                        .iter()
+                        .filter(|sig| sig.sig_type != SigType::Revoke)
+                        .max_by_key(|sig| sig.creation_time);
+                    let revoked = newest.iter().max_by_key(|sig| sig.creation_time);
+                    match (certified, revoked) {
+                        (Some(sig), _) => MatrixCell::Certified {
+                            sig_type: sig.sig_type,
+                            creation_time: sig.creation_time,
+                        },
+                        (None, Some(sig)) => MatrixCell::Revoked {
+                            creation_time: sig.creation_time,
+                        },
+                        (None, None) => MatrixCell::Missing,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    Matrix {
+        keys: keys
+            .iter()
+            .map(|v| (v.id.to_string(), v.primary_user_id.to_string()))
+            .collect(),
+        cells,
+    }
+}
+
+/// `uid (fingerprint)`, to tell apart keys sharing a UID, or the fingerprint alone for a key without UID
+fn key_label(fingerprint: &str, uid: &str) -> String {
+    if uid.is_empty() {
+        fingerprint.to_string()
+    } else {
+        format!("{} ({})", uid, fingerprint)
+    }
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Matrix {
+    pub(crate) fn to_text(&self) -> String {
+        let mut output = String::new();
+        self.keys
+            .iter()
+            .enumerate()
+            .for_each(|(i, (fingerprint, uid))| {
+                writeln!(output, "{:>4}  {}  {}", i + 1, fingerprint, uid).unwrap();
+            });
+        writeln!(output).unwrap();
+
+        let width = self
+            .cells
+            .iter()
+            .flatten()
+            .map(|v| v.text().len())
+            .max()
+            .unwrap_or(0);
+        write!(output, "{:>4}", "").unwrap();
+        (1..=self.keys.len()).for_each(|i| write!(output, "  {:>width$}", i).unwrap());
+        writeln!(output).unwrap();
+        self.cells.iter().enumerate().for_each(|(i, row)| {
+            write!(output, "{:>4}", i + 1).unwrap();
+            row.iter()
+                .for_each(|v| write!(output, "  {:>width$}", v.text()).unwrap());
+            writeln!(output).unwrap();
+        });
+        output
+    }
+
+    pub(crate) fn to_csv(&self) -> String {
+        let mut output = String::new();
+        let header: Vec<String> = std::iter::once("issuer \\ target".to_string())
+            .chain(
+                self.keys
+                    .iter()
+                    .map(|(fingerprint, uid)| key_label(fingerprint, uid)),
+            )
+            .collect();
+        write_row(&mut output, &header, ',');
+        self.keys
+            .iter()
+            .zip(&self.cells)
+            .for_each(|((fingerprint, uid), row)| {
+                let line: Vec<String> = std::iter::once(key_label(fingerprint, uid))
+                    .chain(row.iter().map(|v| v.text()))
+                    .collect();
+                write_row(&mut output, &line, ',');
+            });
+        output
+    }
+
+    pub(crate) fn to_html(&self) -> String {
+        let mut output = String::new();
+        output += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Keysigning party matrix</title>\n<style>\n";
+        output +=
+            "table { border-collapse: collapse; font-family: sans-serif; font-size: small; }\n";
+        output += "th, td { border: 1px solid #ccc; padding: 2px 4px; text-align: center; }\n";
+        output += "td.own { background: #eee; }\ntd.missing { background: #fdd; }\n";
+        output += "td.certified { background: #dfd; }\ntd.revoked { background: #fc9; }\n";
+        output += "</style>\n</head>\n<body>\n<table>\n<tr><th>issuer \\ target</th>";
+        self.keys.iter().for_each(|(fingerprint, uid)| {
+            write!(
+                output,
+                "<th title=\"{}\">{}</th>",
+                escape_html(fingerprint),
+                escape_html(uid)
+            )
+            .unwrap();
+        });
+        output += "</tr>\n";
+        self.keys
+            .iter()
+            .zip(&self.cells)
+            .for_each(|((fingerprint, uid), row)| {
+                write!(
+                    output,
+                    "<tr><th title=\"{}\">{}</th>",
+                    escape_html(fingerprint),
+                    escape_html(uid)
+                )
+                .unwrap();
+                row.iter().for_each(|v| {
+                    write!(
+                        output,
+                        "<td class=\"{}\">{}</td>",
+                        v.class(),
+                        escape_html(&v.text())
+                    )
+                    .unwrap();
+                });
+                output += "</tr>\n";
+            });
+        output += "</table>\n</body>\n</html>\n";
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{certify_with, generate, key_set, signer};
+    use sequoia_openpgp::cert::CertBuilder;
+    use sequoia_openpgp::packet::signature::SignatureBuilder;
+    use sequoia_openpgp::types::SignatureType;
+
+    #[test]
+    fn format_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951782400), "2000-02-29");
+        assert_eq!(format_date(1709251199), "2024-02-29");
+    }
+
+    #[test]
+    fn csv_tells_apart_keys_sharing_a_uid() {
+        let matrix = Matrix {
+            keys: vec![
+                ("AAAA".to_string(), "alice <alice@example.org>".to_string()),
+                ("BBBB".to_string(), "alice <alice@example.org>".to_string()),
+                ("CCCC".to_string(), "".to_string()),
+            ],
+            cells: vec![
+                vec![
+                    MatrixCell::OwnKey,
+                    MatrixCell::Missing,
+                    MatrixCell::Revoked { creation_time: 0 },
+                ],
+                vec![
+                    MatrixCell::Certified {
+                        sig_type: SigType::Careful,
+                        creation_time: 0,
+                    },
+                    MatrixCell::OwnKey,
+                    MatrixCell::Missing,
+                ],
+                vec![MatrixCell::Missing, MatrixCell::Missing, MatrixCell::OwnKey],
+            ],
+        };
+        assert_eq!(
+            matrix.to_csv(),
+            "issuer \\ target,alice <alice@example.org> (AAAA),alice <alice@example.org> (BBBB),CCCC\n\
+             alice <alice@example.org> (AAAA),self,-,Revoked 1970-01-01\n\
+             alice <alice@example.org> (BBBB),Careful 1970-01-01,self,-\n\
+             CCCC,-,-,self\n"
+        );
+    }
+
+    #[test]
+    fn csv_escaping() {
+        let matrix = Matrix {
+            keys: vec![("AAAA".to_string(), "alice, \"al\"\r".to_string())],
+            cells: vec![vec![MatrixCell::OwnKey]],
+        };
+        assert_eq!(
+            matrix.to_csv(),
+            "issuer \\ target,\"alice, \"\"al\"\"\r (AAAA)\"\n\
+             \"alice, \"\"al\"\"\r (AAAA)\",self\n"
+        );
+    }
+
+    #[test]
+    fn revocation_of_one_uid_keeps_the_other() {
+        let bob = generate("bob");
+        let alice = CertBuilder::new()
+            .add_userid("alice")
+            .add_userid("alice work")
+            .generate()
+            .unwrap()
+            .0;
+        let alice = alice
+            .userids()
+            .map(|v| v.userid().clone())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .fold(alice, |alice, uid| {
+                let sig = SignatureBuilder::new(SignatureType::GenericCertification)
+                    .sign_userid_binding(&mut signer(&bob), alice.primary_key().key(), &uid)
+                    .unwrap();
+                alice.insert_packets(sig).unwrap()
+            });
+        let uid = alice.userids().nth(1).unwrap().userid().clone();
+        let revocation = SignatureBuilder::new(SignatureType::CertificationRevocation)
+            .sign_userid_binding(&mut signer(&bob), alice.primary_key().key(), &uid)
+            .unwrap();
+        let alice = alice.insert_packets(revocation).unwrap();
+
+        // alice sorts before bob
+        let matrix = matrix(&key_set(&[&alice, &bob]), &[], false);
+        assert_eq!(matrix.keys[1].0, bob.fingerprint().to_string());
+        assert!(matches!(matrix.cells[1][0], MatrixCell::Certified { .. }));
+    }
+
+    #[test]
+    fn certification_by_key_id() {
+        let bob = generate("bob");
+        let builder = SignatureBuilder::new(SignatureType::GenericCertification)
+            .set_issuer(bob.keyid())
+            .unwrap();
+        let alice = certify_with(generate("alice"), &bob, builder);
+
+        // alice sorts before bob
+        let matrix = matrix(&key_set(&[&alice, &bob]), &[], false);
+        assert_eq!(matrix.keys[1].0, bob.fingerprint().to_string());
+        assert!(matches!(matrix.cells[1][0], MatrixCell::Certified { .. }));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::structure::{GraphNodeUid, OpenPgpSig, SigType};
+use crate::style::{label_lines, Attrs};
+use petgraph::graphmap::DiGraphMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+fn escape_mermaid(value: &str) -> String {
+    value
+        .replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+/// Mermaid style of a node or an edge, from its Graphviz attributes
+fn mermaid_style(attrs: &Attrs, filled: bool) -> Vec<String> {
+    let mut style: Vec<String> = Default::default();
+    if filled {
+        if let Some(fill) = attrs
+            .get("style")
+            .filter(|v| v.split(',').any(|v| v.trim() == "filled"))
+            .and(attrs.get("fillcolor"))
+        {
+            style.push(format!("fill:{}", fill));
+        }
+    }
+    if let Some(color) = attrs.get("color") {
+        style.push(format!("stroke:{}", color));
+    }
+    if let Some(penwidth) = attrs.get("penwidth") {
+        style.push(format!("stroke-width:{}px", penwidth));
+    }
+    if attrs
+        .get("style")
+        .is_some_and(|v| v.split(',').any(|v| v.trim() == "dashed"))
+    {
+        style.push("stroke-dasharray:5 3".to_string());
+    }
+    if let Some(fontcolor) = attrs.get("fontcolor") {
+        style.push(format!("color:{}", fontcolor));
+    }
+    style
+}
+
+/// Render the graph as a Mermaid flowchart, with short node IDs and name/email labels
+pub(crate) fn to_mermaid<'a, N, E>(
+    graph: &DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>,
+    node_attrs: N,
+    edge_attrs: E,
+) -> String
+where
+    N: Fn(&GraphNodeUid) -> Attrs,
+    E: Fn(&GraphNodeUid, &GraphNodeUid, &OpenPgpSig) -> Attrs,
+{
+    let index: HashMap<GraphNodeUid, usize> =
+        graph.nodes().enumerate().map(|(i, v)| (v, i)).collect();
+    let mut output = String::from("flowchart LR\n");
+    let mut styles = String::new();
+
+    graph.nodes().enumerate().for_each(|(i, uid)| {
+        let label: Vec<String> = label_lines(&uid)
+            .iter()
+            .map(|v| escape_mermaid(v))
+            .collect();
+        writeln!(output, "    n{}[\"{}\"]", i, label.join("<br/>")).unwrap();
+        let style = mermaid_style(&node_attrs(&uid), true);
+        if !style.is_empty() {
+            writeln!(styles, "    style n{} {}", i, style.join(",")).unwrap();
+        }
+    });
+
+    graph
+        .all_edges()
+        .enumerate()
+        .for_each(|(i, (issuer, target, sig))| {
+            let arrow = match sig.sig_type {
+                SigType::NotAtAll => "--o",
+                SigType::Casual => "-.->",
+                SigType::Careful => "==>",
+                SigType::Revoke => "--x",
+                SigType::Default | SigType::Unknown(_) => "-->",
+            };
+            let label = sig
+                .trust_label()
+                .map_or_else(Default::default, |v| format!("|{}|", v));
+            writeln!(
+                output,
+                "    n{} {}{} n{}",
+                index[&issuer], arrow, label, index[&target]
+            )
+            .unwrap();
+            let style = mermaid_style(&edge_attrs(&issuer, &target, sig), false);
+            if !style.is_empty() {
+                writeln!(styles, "    linkStyle {} {}", i, style.join(",")).unwrap();
+            }
+        });
+
+    output += &styles;
+    output
+}
@@ -0,0 +1,122 @@
+use crate::structure::{GraphNodeUid, OpenPgpSig, SigType};
+use crate::style::{label_lines, Attrs};
+use petgraph::graphmap::DiGraphMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// PlantUML colors are either `#RRGGBB` or a named color prefixed with `#`
+fn plantuml_color(color: &str) -> String {
+    format!("#{}", color.trim_start_matches('#'))
+}
+
+fn is_style(attrs: &Attrs, style: &str) -> bool {
+    attrs
+        .get("style")
+        .is_some_and(|v| v.split(',').any(|v| v.trim() == style))
+}
+
+/// Inline style of an element, e.g. `#gold;line:orange;line.bold`
+fn node_style(attrs: &Attrs) -> String {
+    let mut style: Vec<String> = Default::default();
+    if let Some(fill) = attrs.get("fillcolor").filter(|_| is_style(attrs, "filled")) {
+        style.push(plantuml_color(fill));
+    }
+    if let Some(color) = attrs.get("color") {
+        style.push(format!("line:{}", color));
+    }
+    if is_style(attrs, "dashed") {
+        style.push("line.dashed".to_string());
+    }
+    // There are no double borders, so the trust root is drawn in bold too
+    let bold = |key| {
+        attrs
+            .get(key)
+            .and_then(|v| v.parse::<f64>().ok())
+            .is_some_and(|v| v > 1.0)
+    };
+    if bold("penwidth") || bold("peripheries") {
+        style.push("line.bold".to_string());
+    }
+    if let Some(fontcolor) = attrs.get("fontcolor") {
+        style.push(format!("text:{}", fontcolor));
+    }
+    match style.first() {
+        None => Default::default(),
+        Some(v) if v.starts_with('#') => format!(" {}", style.join(";")),
+        Some(_) => format!(" #{}", style.join(";")),
+    }
+}
+
+/// Arrow of an edge, e.g. `-[#red,dashed]->`
+fn edge_arrow(sig: &OpenPgpSig, attrs: &Attrs) -> String {
+    let mut style: Vec<String> = Default::default();
+    if let Some(color) = attrs.get("color") {
+        style.push(plantuml_color(color));
+    }
+    match sig.sig_type {
+        SigType::NotAtAll => style.push("dotted".to_string()),
+        SigType::Casual => style.push("dashed".to_string()),
+        SigType::Careful => style.push("bold".to_string()),
+        SigType::Default | SigType::Revoke | SigType::Unknown(_) => {}
+    }
+    if is_style(attrs, "dashed") && !style.iter().any(|v| v == "dashed") {
+        style.push("dashed".to_string());
+    }
+    if style.is_empty() {
+        "-->".to_string()
+    } else {
+        format!("-[{}]->", style.join(","))
+    }
+}
+
+/// Render the graph as a PlantUML diagram, with short node IDs and name/email labels
+pub(crate) fn to_plantuml<'a, N, E>(
+    graph: &DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>,
+    node_attrs: N,
+    edge_attrs: E,
+) -> String
+where
+    N: Fn(&GraphNodeUid) -> Attrs,
+    E: Fn(&GraphNodeUid, &GraphNodeUid, &OpenPgpSig) -> Attrs,
+{
+    let index: HashMap<GraphNodeUid, usize> =
+        graph.nodes().enumerate().map(|(i, v)| (v, i)).collect();
+    let mut output = String::from("@startuml\nleft to right direction\n");
+
+    graph.nodes().enumerate().for_each(|(i, uid)| {
+        let label: Vec<String> = label_lines(&uid)
+            .iter()
+            .map(|v| v.replace('"', "'"))
+            .collect();
+        writeln!(
+            output,
+            "rectangle \"{}\" as n{}{}",
+            label.join("\\n"),
+            i,
+            node_style(&node_attrs(&uid))
+        )
+        .unwrap();
+    });
+
+    graph.all_edges().for_each(|(issuer, target, sig)| {
+        let attrs = edge_attrs(&issuer, &target, sig);
+        write!(
+            output,
+            "n{} {} n{}",
+            index[&issuer],
+            edge_arrow(sig, &attrs),
+            index[&target]
+        )
+        .unwrap();
+        if sig.sig_type == SigType::Revoke {
+            output += " : revoked";
+        } else if let Some(label) = sig.trust_label() {
+            output += " : ";
+            output += &label;
+        }
+        output += "\n";
+    });
+
+    output += "@enduml\n";
+    output
+}
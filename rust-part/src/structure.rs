@@ -2,11 +2,12 @@ use crate::cert::{complex_output, get_pgp_uid_by_node_uid, simple_output};
 use crate::GOSSIP_LAYER_MAP;
 use num_enum::{FromPrimitive, IntoPrimitive};
 use sequoia_openpgp::types::SignatureType;
+use sequoia_openpgp::{Fingerprint, KeyID};
 use serde::Serialize;
 use serialize_display_adapter_macro_derive::JsonSerializeDisplayAdapter;
 use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
@@ -14,9 +15,76 @@ use std::sync::Arc;
 pub(crate) struct OpenPgpKey {
     pub(crate) id: Arc<String>,
     pub(crate) is_revoked: bool,
+    /// The newest revocation signature, by the key itself or by a designated revoker,
+    /// the latter can't be verified without the key of the revoker, so it doesn't make the key revoked
+    #[serde(skip_serializing)]
+    pub(crate) revocation: Option<OpenPgpSig>,
     pub(crate) is_expired: bool,
     pub(crate) user_ids: HashMap<Arc<String>, OpenPgpUid>,
+    /// User attributes, e.g. photo IDs, by a label like `[jpeg image #1]`
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) user_attributes: HashMap<Arc<String>, OpenPgpUid>,
     pub(crate) primary_user_id: Arc<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) invalid_reason: Option<String>,
+    pub(crate) primary_key: OpenPgpKeyMaterial,
+    pub(crate) subkeys: Vec<OpenPgpKeyMaterial>,
+}
+
+impl OpenPgpKey {
+    /// The UID, or the user attribute, with this label
+    pub(crate) fn uid(&self, uid: &str) -> Option<&OpenPgpUid> {
+        let uid = uid.to_string();
+        self.user_ids
+            .get(&uid)
+            .or_else(|| self.user_attributes.get(&uid))
+    }
+
+    /// Capabilities of the primary key and subkeys which are neither expired nor revoked
+    pub(crate) fn usable_capabilities(&self) -> BTreeSet<OpenPgpKeyCapability> {
+        std::iter::once(&self.primary_key)
+            .chain(self.subkeys.iter())
+            .filter(|v| !v.is_expired && !v.is_revoked)
+            .flat_map(|v| v.capabilities.iter().copied())
+            .collect()
+    }
+}
+
+/// A primary key or subkey: its algorithm, validity period and what it can be used for
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub(crate) struct OpenPgpKeyMaterial {
+    pub(crate) fingerprint: String,
+    pub(crate) algorithm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) bits: Option<usize>,
+    pub(crate) capabilities: Vec<OpenPgpKeyCapability>,
+    pub(crate) creation_time: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) expiration_time: Option<u64>,
+    pub(crate) is_expired: bool,
+    pub(crate) is_revoked: bool,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OpenPgpKeyCapability {
+    Certify,
+    Sign,
+    EncryptForTransport,
+    EncryptAtRest,
+    Authenticate,
+}
+
+impl OpenPgpKeyCapability {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            OpenPgpKeyCapability::Certify => "certify",
+            OpenPgpKeyCapability::Sign => "sign",
+            OpenPgpKeyCapability::EncryptForTransport => "encrypt-for-transport",
+            OpenPgpKeyCapability::EncryptAtRest => "encrypt-at-rest",
+            OpenPgpKeyCapability::Authenticate => "authenticate",
+        }
+    }
 }
 
 impl PartialEq for OpenPgpKey {
@@ -47,7 +115,29 @@ pub(crate) struct OpenPgpUid {
     #[serde(skip_serializing)]
     pub(crate) sig_vec: Vec<OpenPgpSig>,
     pub(crate) is_revoked: bool,
+    /// The newest revocation signature
+    #[serde(skip_serializing)]
+    pub(crate) revocation: Option<OpenPgpSig>,
     pub(crate) is_primary: bool,
+    /// JPEG image of a photo ID
+    #[serde(skip_serializing)]
+    pub(crate) photo: Option<OpenPgpPhoto>,
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub(crate) struct OpenPgpPhoto {
+    /// Name of the file the image is extracted to, `<fingerprint>-<n>.jpg`
+    pub(crate) file_name: String,
+    pub(crate) jpeg: Arc<Vec<u8>>,
+}
+
+impl Debug for OpenPgpPhoto {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenPgpPhoto")
+            .field("file_name", &self.file_name)
+            .field("jpeg", &format_args!("{} bytes", self.jpeg.len()))
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -235,9 +325,15 @@ impl<'a> From<&'a OpenPgpSig> for GraphNodeUid<'a> {
 #[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub(crate) struct OpenPgpSig {
     pub(crate) fingerprint: String,
+    /// Key ID of the issuer, the only way to know it when the signature names no fingerprint
+    #[serde(skip_serializing)]
+    pub(crate) issuer_keyid: String,
     pub(crate) uid: String,
     pub(crate) trust_level: u8,
     pub(crate) trust_value: OpenPgpSigTrust,
+    /// Regular expressions limiting the UIDs a trust signature delegates to
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) regex: Vec<String>,
     pub(crate) sig_type: SigType,
     pub(crate) creation_time: u64,
 }
@@ -246,6 +342,8 @@ pub(crate) struct OpenPgpSig {
 pub(crate) struct GraphEdgeSig {
     pub(crate) trust_level: u8,
     pub(crate) trust_value: OpenPgpSigTrust,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) regex: Vec<String>,
     pub(crate) sig_type: SigType,
     pub(crate) creation_time: u64,
 }
@@ -255,18 +353,48 @@ impl From<&OpenPgpSig> for GraphEdgeSig {
         GraphEdgeSig {
             trust_level: value.trust_level,
             trust_value: value.trust_value,
+            regex: value.regex.clone(),
             sig_type: value.sig_type,
             creation_time: value.creation_time,
         }
     }
 }
 
+impl OpenPgpSig {
+    /// Whether the key of `fingerprint` made this signature, by the key ID if there is no issuer fingerprint
+    pub(crate) fn is_issued_by(&self, fingerprint: &str) -> bool {
+        if self.fingerprint.is_empty() {
+            Fingerprint::from_hex(fingerprint)
+                .is_ok_and(|v| KeyID::from(v).to_hex() == self.issuer_keyid)
+        } else {
+            self.fingerprint == fingerprint
+        }
+    }
+
+    /// Depth, amount and scope of a trust signature, which makes the target a trusted introducer
+    pub(crate) fn trust_label(&self) -> Option<String> {
+        (self.trust_level > 0).then(|| {
+            let mut label = format!(
+                "introducer depth {}, {:?}",
+                self.trust_level, self.trust_value
+            );
+            if !self.regex.is_empty() {
+                label += &format!(", scope {}", self.regex.join(" "));
+            }
+            label
+        })
+    }
+}
+
 impl Display for OpenPgpSig {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sig_type = self.sig_type.to_string().replace("\"", "");
         simple_output(
             &<&OpenPgpSig as Into<GraphEdgeSig>>::into(self),
             f,
-            &self.sig_type.to_string().replace("\"", ""),
+            &self
+                .trust_label()
+                .map_or(sig_type.clone(), |v| format!("{}, {}", sig_type, v)),
         )
     }
 }
@@ -296,6 +424,19 @@ pub(crate) enum OpenPgpValidity {
     Ultimate = 5,
 }
 
+impl OpenPgpValidity {
+    pub(crate) fn color(&self) -> &'static str {
+        match self {
+            OpenPgpValidity::Unknown => "white",
+            OpenPgpValidity::Undefined => "lightgray",
+            OpenPgpValidity::Never => "lightpink",
+            OpenPgpValidity::Marginal => "khaki",
+            OpenPgpValidity::Full => "palegreen",
+            OpenPgpValidity::Ultimate => "gold",
+        }
+    }
+}
+
 #[derive(
     FromPrimitive,
     IntoPrimitive,
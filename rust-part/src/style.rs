@@ -0,0 +1,464 @@
+use crate::ca;
+use crate::cert::get_pgp_uid_by_node_uid;
+use crate::structure::{GraphNodeUid, OpenPgpSig, SigType};
+use crate::{AUDIT_MAP, CLI_ARGS, GOSSIP_LAYER_MAP, KEY_SET_MAP, OWNER_TRUST_MAP, THEME};
+use anyhow::anyhow;
+use anyhow::Context;
+use clio::ClioPath;
+use petgraph::graphmap::DiGraphMap;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Graphviz attributes of a node or an edge, setting an attribute again replaces its value
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize)]
+#[serde(from = "BTreeMap<String, AttrValue>")]
+pub(crate) struct Attrs(Vec<(String, String)>);
+
+/// Value of an attribute in the style file, Graphviz takes all of them as strings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum AttrValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl From<BTreeMap<String, AttrValue>> for Attrs {
+    fn from(value: BTreeMap<String, AttrValue>) -> Self {
+        let mut attrs: Attrs = Default::default();
+        value.into_iter().for_each(|(k, v)| match v {
+            AttrValue::String(v) => attrs.set(&k, v),
+            AttrValue::Integer(v) => attrs.set(&k, v),
+            AttrValue::Float(v) => attrs.set(&k, v),
+            AttrValue::Boolean(v) => attrs.set(&k, v),
+        });
+        attrs
+    }
+}
+
+impl Attrs {
+    pub(crate) fn set(&mut self, key: &str, value: impl ToString) {
+        let value = value.to_string();
+        match self.0.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key.to_string(), value)),
+        }
+    }
+
+    /// Set the attribute only if it isn't set yet
+    pub(crate) fn set_default(&mut self, key: &str, value: impl ToString) {
+        if self.get(key).is_none() {
+            self.set(key, value);
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Add a style to the comma-separated `style` list, keeping the styles already set
+    pub(crate) fn add_style(&mut self, style: &str) {
+        match self.get("style").filter(|v| !v.trim().is_empty()) {
+            Some(v) if v.split(',').any(|v| v.trim() == style) => {}
+            Some(v) => self.set("style", format!("{},{}", v, style)),
+            None => self.set("style", style),
+        }
+    }
+
+    /// Set all attributes of `other`, replacing the values of those already set
+    pub(crate) fn merge(&mut self, other: &Attrs) {
+        other.0.iter().for_each(|(k, v)| self.set(k, v));
+    }
+}
+
+impl<const N: usize> From<[(&str, &str); N]> for Attrs {
+    fn from(value: [(&str, &str); N]) -> Self {
+        let mut attrs: Attrs = Default::default();
+        value.iter().for_each(|(k, v)| attrs.set(k, v));
+        attrs
+    }
+}
+
+/// Attributes of the node states and edge types, read from a TOML style file, e.g.
+///
+/// ```toml
+/// [node.revoked]
+/// color = "red"
+/// [node.layer.1]
+/// fillcolor = "lightblue"
+/// [edge.careful]
+/// penwidth = 2
+/// ```
+///
+/// The attributes of a state given in the file are merged into the default ones
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Theme {
+    pub(crate) node: NodeTheme,
+    pub(crate) edge: EdgeTheme,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct NodeTheme {
+    pub(crate) revoked: Attrs,
+    pub(crate) expired: Attrs,
+    /// The trust root, or the keys gossiped from
+    pub(crate) root: Attrs,
+    /// Keys by gossip layer, a layer not given takes the attributes of the nearest one given before it
+    pub(crate) layer: BTreeMap<String, Attrs>,
+    pub(crate) unknown: Attrs,
+    pub(crate) invalid: Attrs,
+    /// CA keys of organizations using OpenPGP CA
+    pub(crate) ca: Attrs,
+    /// User attributes, e.g. photo IDs
+    pub(crate) photo: Attrs,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct EdgeTheme {
+    pub(crate) default: Attrs,
+    pub(crate) not_at_all: Attrs,
+    pub(crate) casual: Attrs,
+    pub(crate) careful: Attrs,
+    pub(crate) revoke: Attrs,
+    pub(crate) unknown: Attrs,
+    /// Trust signatures, making the target a trusted introducer
+    pub(crate) trust: Attrs,
+    /// Certifications whose issuer is invalid or unknown
+    pub(crate) invalid: Attrs,
+    /// Trust signatures between the CAs of organizations
+    pub(crate) bridge: Attrs,
+}
+
+/// Fill colors of the gossip layers, fading out from the roots
+const LAYER_PALETTE: [&str; 5] = ["#6baed6", "#9ecae1", "#c6dbef", "#deebf7", "#f7fbff"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            node: NodeTheme {
+                revoked: [("color", "red")].into(),
+                layer: LAYER_PALETTE
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        (
+                            i.to_string(),
+                            [("style", "filled"), ("fillcolor", v)].into(),
+                        )
+                    })
+                    .collect(),
+                root: [("peripheries", "2")].into(),
+                unknown: [
+                    ("shape", "box"),
+                    ("style", "filled"),
+                    ("fillcolor", "lightgray"),
+                    ("color", "gray"),
+                    ("fontcolor", "dimgray"),
+                ]
+                .into(),
+                invalid: [
+                    ("style", "dashed"),
+                    ("color", "gray"),
+                    ("fontcolor", "gray"),
+                ]
+                .into(),
+                ca: [("shape", "doubleoctagon")].into(),
+                photo: [("shape", "box")].into(),
+                ..Default::default()
+            },
+            edge: EdgeTheme {
+                revoke: [("color", "red")].into(),
+                trust: [("color", "blue"), ("penwidth", "2")].into(),
+                invalid: [("style", "dashed"), ("color", "gray")].into(),
+                bridge: [("color", "purple"), ("penwidth", "3")].into(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Theme {
+    fn merge(&mut self, other: &Theme) {
+        let node = &mut self.node;
+        node.revoked.merge(&other.node.revoked);
+        node.expired.merge(&other.node.expired);
+        node.root.merge(&other.node.root);
+        other.node.layer.iter().for_each(|(k, v)| {
+            node.layer.entry(k.clone()).or_default().merge(v);
+        });
+        node.unknown.merge(&other.node.unknown);
+        node.invalid.merge(&other.node.invalid);
+        node.ca.merge(&other.node.ca);
+        node.photo.merge(&other.node.photo);
+
+        let edge = &mut self.edge;
+        edge.default.merge(&other.edge.default);
+        edge.not_at_all.merge(&other.edge.not_at_all);
+        edge.casual.merge(&other.edge.casual);
+        edge.careful.merge(&other.edge.careful);
+        edge.revoke.merge(&other.edge.revoke);
+        edge.unknown.merge(&other.edge.unknown);
+        edge.trust.merge(&other.edge.trust);
+        edge.invalid.merge(&other.edge.invalid);
+        edge.bridge.merge(&other.edge.bridge);
+    }
+
+    fn layer(&self, layer: u8) -> Option<&Attrs> {
+        self.node
+            .layer
+            .iter()
+            .filter_map(|(k, v)| k.parse::<u8>().ok().map(|k| (k, v)))
+            .filter(|(k, _)| *k <= layer)
+            .max_by_key(|(k, _)| *k)
+            .map(|(_, v)| v)
+    }
+
+    fn sig_type(&self, sig_type: SigType) -> &Attrs {
+        match sig_type {
+            SigType::Default => &self.edge.default,
+            SigType::NotAtAll => &self.edge.not_at_all,
+            SigType::Casual => &self.edge.casual,
+            SigType::Careful => &self.edge.careful,
+            SigType::Revoke => &self.edge.revoke,
+            SigType::Unknown(_) => &self.edge.unknown,
+        }
+    }
+}
+
+/// Read a style file, merged into the default theme
+pub(crate) fn read_theme(path: &ClioPath) -> anyhow::Result<Theme> {
+    let content = std::fs::read_to_string(path.path())
+        .with_context(|| format!("Failed to read style: {}", path.display()))?;
+    let style: Theme = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse style: {}", path.display()))?;
+    if let Some(layer) = style.node.layer.keys().find(|v| v.parse::<u8>().is_err()) {
+        return Err(anyhow!(
+            "Failed to parse style: {}: invalid layer `{}`",
+            path.display(),
+            layer
+        ));
+    }
+    let mut theme: Theme = Default::default();
+    theme.merge(&style);
+    Ok(theme)
+}
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(Default::default)
+}
+
+/// Quote a DOT ID unless it's a plain identifier or numeral
+fn dot_id(value: &str) -> String {
+    let identifier = value
+        .chars()
+        .next()
+        .is_some_and(|v| v.is_ascii_alphabetic() || v == '_')
+        && value.chars().all(|v| v.is_ascii_alphanumeric() || v == '_');
+    let numeral = !value.is_empty() && value.chars().all(|v| v.is_ascii_digit() || v == '.');
+    if identifier || numeral {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    }
+}
+
+impl Display for Attrs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|(k, v)| write!(f, " {} = {} ", k, dot_id(v)))
+    }
+}
+
+/// Attributes of a UID node: the theme of its states, and its audit, ownertrust and gossip root
+pub(crate) fn node_attrs(uid: &GraphNodeUid, trust_root: Option<&str>) -> Attrs {
+    let theme = theme();
+    let mut attrs: Attrs = Default::default();
+    let fingerprint = uid.fingerprint.to_string();
+    let pgp_key = KEY_SET_MAP.get().and_then(|map| map.get(&fingerprint));
+    let layer = GOSSIP_LAYER_MAP
+        .get()
+        .and_then(|map| map.get(&fingerprint))
+        .filter(|_| CLI_ARGS.get().is_some_and(|args| args.gossip.is_some()));
+
+    if let Some(attrs_of_layer) = layer.and_then(|v| theme.layer(*v)) {
+        attrs.merge(attrs_of_layer);
+    }
+    if pgp_key.is_some_and(|v| v.is_expired) {
+        attrs.merge(&theme.node.expired);
+    }
+    if pgp_key.is_some_and(|v| v.user_attributes.keys().any(|k| k.as_str() == uid.uid)) {
+        attrs.merge(&theme.node.photo);
+    }
+    if let (Some(directory), Some(photo)) = (
+        CLI_ARGS.get().and_then(|args| args.extract_photos.as_ref()),
+        get_pgp_uid_by_node_uid(uid).and_then(|v| v.photo.as_ref()),
+    ) {
+        attrs.set("image", directory.path().join(&photo.file_name).display());
+        attrs.set("labelloc", "b");
+    }
+    if get_pgp_uid_by_node_uid(uid).is_some_and(|v| v.is_revoked) {
+        attrs.merge(&theme.node.revoked);
+    }
+    if KEY_SET_MAP.get().is_some() && pgp_key.is_none() {
+        attrs.merge(&theme.node.unknown);
+        attrs.set("unknown", "true");
+    }
+    if let Some(reason) = pgp_key.and_then(|v| v.invalid_reason.as_ref()) {
+        attrs.merge(&theme.node.invalid);
+        attrs.set("invalid", reason);
+    }
+    if let Some(organization) = ca::organization_of_ca(uid.fingerprint) {
+        attrs.merge(&theme.node.ca);
+        attrs.set("ca", &organization.domain);
+    }
+    if trust_root == Some(uid.fingerprint) || layer == Some(&0) {
+        attrs.merge(&theme.node.root);
+    }
+    if let Some(entry) = AUDIT_MAP.get().and_then(|map| map.get(&fingerprint)) {
+        if !entry.issues.is_empty() {
+            let issues: Vec<&str> = entry.issues.iter().map(|v| v.name()).collect();
+            attrs.set_default("color", "orange");
+            attrs.set("penwidth", 3);
+            attrs.set("audit", issues.join(","));
+        }
+    }
+    if let Some(ownertrust) = OWNER_TRUST_MAP.get().and_then(|map| map.get(&fingerprint)) {
+        // Keep the styles of the states above, e.g. dashed for invalid keys
+        attrs.add_style("filled");
+        attrs.set("fillcolor", ownertrust.color());
+        attrs.set("ownertrust", format!("{:?}", ownertrust));
+    }
+    if layer == Some(&0) {
+        attrs.set("root", "true");
+    }
+    attrs
+}
+
+/// Attributes of a certification edge: the theme of its signature type, trust signature, bridge between CAs
+/// and issuer invalid or unknown
+pub(crate) fn edge_attrs(issuer: &GraphNodeUid, target: &GraphNodeUid, sig: &OpenPgpSig) -> Attrs {
+    let theme = theme();
+    let mut attrs = theme.sig_type(sig.sig_type).clone();
+    let issuer_key = KEY_SET_MAP.get().map(|map| map.get(&sig.fingerprint));
+    let invalid_issuer = issuer_key
+        .flatten()
+        .is_some_and(|v| v.invalid_reason.is_some());
+    let unknown_issuer = issuer_key.is_some_and(|v| v.is_none());
+    if sig.trust_level > 0 {
+        attrs.merge(&theme.edge.trust);
+        attrs.set("class", "introducer");
+    }
+    if ca::is_bridge(issuer, target, sig) {
+        attrs.merge(&theme.edge.bridge);
+        attrs.set("class", "bridge");
+    }
+    if sig.sig_type != SigType::Revoke && (invalid_issuer || unknown_issuer) {
+        attrs.merge(&theme.edge.invalid);
+    }
+    attrs
+}
+
+/// `rank = same` subgraphs of the nodes in each gossip layer, for Graphviz to lay them out in rows from the roots
+pub(crate) fn layer_ranks(graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>) -> String {
+    let Some(layer_map) = GOSSIP_LAYER_MAP
+        .get()
+        .filter(|_| CLI_ARGS.get().is_some_and(|args| args.gossip.is_some()))
+    else {
+        return Default::default();
+    };
+    let mut layers: BTreeMap<u8, Vec<String>> = Default::default();
+    graph.nodes().enumerate().for_each(|(i, uid)| {
+        if let Some(layer) = layer_map.get(&uid.fingerprint.to_string()) {
+            layers.entry(*layer).or_default().push(i.to_string());
+        }
+    });
+    layers
+        .values()
+        .map(|v| format!("    {{ rank = same; {}; }}\n", v.join("; ")))
+        .collect()
+}
+
+/// Lines of a compact node label: the name and email of the UID, the whole UID if it has neither,
+/// or the fingerprint of an unknown key
+pub(crate) fn label_lines(uid: &GraphNodeUid) -> Vec<String> {
+    match get_pgp_uid_by_node_uid(uid) {
+        None => vec![uid.fingerprint.to_string()],
+        Some(pgp_uid) => {
+            let lines: Vec<String> = [&pgp_uid.name, &pgp_uid.email]
+                .into_iter()
+                .filter(|v| !v.is_empty())
+                .cloned()
+                .collect();
+            if lines.is_empty() {
+                vec![pgp_uid.uid.to_string()]
+            } else {
+                lines
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn add_style_keeps_styles() {
+        let mut attrs: Attrs = [("style", "dashed"), ("color", "gray")].into();
+        attrs.add_style("filled");
+        assert_eq!(attrs.get("style"), Some("dashed,filled"));
+        attrs.add_style("filled");
+        assert_eq!(attrs.get("style"), Some("dashed,filled"));
+
+        let mut attrs: Attrs = Default::default();
+        attrs.add_style("filled");
+        assert_eq!(attrs.get("style"), Some("filled"));
+    }
+
+    #[test]
+    fn layer_falls_back_to_previous() {
+        let theme = Theme::default();
+        assert_eq!(theme.layer(0), theme.node.layer.get("0"));
+        assert_eq!(theme.layer(200), theme.layer(4));
+    }
+
+    fn write_style(name: &str, content: &str) -> ClioPath {
+        let path = temp_path(&format!("{}.toml", name));
+        std::fs::write(&path, content).unwrap();
+        ClioPath::new(path).unwrap()
+    }
+
+    #[test]
+    fn style_file_is_merged_into_defaults() {
+        let path = write_style(
+            "style",
+            "[node.layer.1]\nfillcolor = \"lightblue\"\n\n[edge.careful]\npenwidth = 2\n",
+        );
+        let theme = read_theme(&path).unwrap();
+        std::fs::remove_file(path.path()).ok();
+
+        let layer = theme.layer(1).unwrap();
+        assert_eq!(layer.get("fillcolor"), Some("lightblue"));
+        assert_eq!(layer.get("style"), Some("filled"));
+        assert_eq!(theme.edge.careful.get("penwidth"), Some("2"));
+        assert_eq!(theme.edge.revoke.get("color"), Some("red"));
+    }
+
+    #[test]
+    fn style_file_with_invalid_layer() {
+        let path = write_style("style-layer", "[node.layer.first]\ncolor = \"red\"\n");
+        let result = read_theme(&path);
+        std::fs::remove_file(path.path()).ok();
+        assert!(result.is_err());
+    }
+}
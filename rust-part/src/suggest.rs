@@ -0,0 +1,246 @@
+use crate::analyze::{distances, key_graph};
+use crate::structure::{GraphNodeUid, OpenPgpKey, OpenPgpSig};
+use anyhow::anyhow;
+use petgraph::graphmap::DiGraphMap;
+use petgraph::Direction;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub(crate) enum SuggestReason {
+    JoinComponents,
+    ReduceDistance,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Suggestion {
+    pub(crate) issuer: String,
+    pub(crate) issuer_uid: String,
+    pub(crate) target: String,
+    pub(crate) target_uid: String,
+    pub(crate) reason: SuggestReason,
+    pub(crate) mean_distance_before: Option<f64>,
+    pub(crate) mean_distance_after: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SuggestReport {
+    pub(crate) attendees: usize,
+    pub(crate) components: usize,
+    pub(crate) root: Option<String>,
+    pub(crate) mean_distance: Option<f64>,
+    pub(crate) unreachable: usize,
+    pub(crate) suggestions: Vec<Suggestion>,
+}
+
+/// Weakly connected components of the attendees, largest first
+fn components<'a>(graph: &DiGraphMap<&'a str, ()>, attendees: &[&'a str]) -> Vec<Vec<&'a str>> {
+    let attendee_set: HashSet<&str> = attendees.iter().copied().collect();
+    let mut visited: HashSet<&str> = Default::default();
+    let mut components: Vec<Vec<&str>> = Default::default();
+    attendees.iter().for_each(|attendee| {
+        if !visited.insert(attendee) {
+            return;
+        }
+        let mut component: Vec<&str> = Default::default();
+        let mut queue: VecDeque<&str> = VecDeque::from([*attendee]);
+        while let Some(node) = queue.pop_front() {
+            component.push(node);
+            graph
+                .neighbors_directed(node, Direction::Outgoing)
+                .chain(graph.neighbors_directed(node, Direction::Incoming))
+                .filter(|v| attendee_set.contains(v))
+                .for_each(|v| {
+                    if visited.insert(v) {
+                        queue.push_back(v);
+                    }
+                });
+        }
+        component.sort();
+        components.push(component);
+    });
+    components.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    components
+}
+
+/// The best connected key of a component, used as the endpoint of the suggested certifications
+fn hub<'a>(graph: &DiGraphMap<&'a str, ()>, component: &[&'a str]) -> &'a str {
+    component
+        .iter()
+        .copied()
+        .max_by(|a, b| {
+            let degree = |v: &str| {
+                graph.neighbors_directed(v, Direction::Incoming).count()
+                    + graph.neighbors_directed(v, Direction::Outgoing).count()
+            };
+            degree(a).cmp(&degree(b)).then_with(|| b.cmp(a))
+        })
+        .unwrap_or_default()
+}
+
+/// Mean distance from the root to the attendees, an unreachable attendee counts as the number of attendees
+fn mean_distance(graph: &DiGraphMap<&str, ()>, root: &str, attendees: &[&str]) -> (f64, usize) {
+    let distances = distances(graph, root, Direction::Outgoing);
+    let penalty = attendees.len();
+    let targets: Vec<&&str> = attendees.iter().filter(|v| **v != root).collect();
+    let (sum, unreachable) =
+        targets
+            .iter()
+            .fold((0, 0), |(sum, unreachable), v| match distances.get(**v) {
+                Some(distance) => (sum + distance, unreachable),
+                None => (sum + penalty, unreachable + 1),
+            });
+    if targets.is_empty() {
+        (0.0, 0)
+    } else {
+        (sum as f64 / targets.len() as f64, unreachable)
+    }
+}
+
+pub(crate) fn suggest(
+    graph: &DiGraphMap<GraphNodeUid, &OpenPgpSig>,
+    key_set: &HashMap<Arc<String>, OpenPgpKey>,
+    attendees: &[String],
+    root: Option<&str>,
+) -> anyhow::Result<SuggestReport> {
+    let mut key_graph = key_graph(graph);
+    let mut attendees: Vec<&str> = key_graph
+        .nodes()
+        .filter(|v| attendees.iter().any(|attendee| attendee == v))
+        .collect();
+    attendees.sort();
+    let root = root
+        .map(|root| {
+            key_graph
+                .nodes()
+                .find(|v| *v == root)
+                .ok_or(anyhow!("Root key not found in the graph: {}", root))
+        })
+        .transpose()?;
+
+    let uid = |fingerprint: &str| {
+        key_set
+            .get(&fingerprint.to_string())
+            .map(|v| v.primary_user_id.to_string())
+            .unwrap_or_default()
+    };
+    let suggestion = |issuer: &str, target: &str, reason, before, after| Suggestion {
+        issuer: issuer.to_string(),
+        issuer_uid: uid(issuer),
+        target: target.to_string(),
+        target_uid: uid(target),
+        reason,
+        mean_distance_before: before,
+        mean_distance_after: after,
+    };
+
+    let components = components(&key_graph, &attendees);
+    let mut suggestions: Vec<Suggestion> = Default::default();
+
+    // Join every smaller component to the largest one in both directions
+    if let Some((largest, others)) = components.split_first() {
+        let largest_hub = hub(&key_graph, largest);
+        others.iter().for_each(|component| {
+            let component_hub = hub(&key_graph, component);
+            suggestions.push(suggestion(
+                component_hub,
+                largest_hub,
+                SuggestReason::JoinComponents,
+                None,
+                None,
+            ));
+            suggestions.push(suggestion(
+                largest_hub,
+                component_hub,
+                SuggestReason::JoinComponents,
+                None,
+                None,
+            ));
+        });
+    }
+
+    let (mean_distance_before, unreachable) = root
+        .map(|root| mean_distance(&key_graph, root, &attendees))
+        .map_or((None, 0), |(mean, unreachable)| (Some(mean), unreachable));
+
+    if let (Some(root), Some(before)) = (root, mean_distance_before) {
+        let mut candidates: Vec<(&str, &str, f64)> = Default::default();
+        attendees.iter().for_each(|issuer| {
+            attendees.iter().for_each(|target| {
+                if issuer == target || key_graph.contains_edge(issuer, target) {
+                    return;
+                }
+                key_graph.add_edge(issuer, target, ());
+                let (after, _) = mean_distance(&key_graph, root, &attendees);
+                key_graph.remove_edge(issuer, target);
+                if after < before {
+                    candidates.push((issuer, target, after));
+                }
+            });
+        });
+        candidates.sort_by(|a, b| {
+            a.2.partial_cmp(&b.2)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| (a.0, a.1).cmp(&(b.0, b.1)))
+        });
+        suggestions.extend(candidates.into_iter().map(|(issuer, target, after)| {
+            suggestion(
+                issuer,
+                target,
+                SuggestReason::ReduceDistance,
+                Some(before),
+                Some(after),
+            )
+        }));
+    }
+
+    Ok(SuggestReport {
+        attendees: attendees.len(),
+        components: components.len(),
+        root: root.map(|v| v.to_string()),
+        mean_distance: mean_distance_before,
+        unreachable,
+        suggestions,
+    })
+}
+
+impl Display for SuggestReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Attendees:\t\t{}", self.attendees)?;
+        writeln!(f, "Components:\t\t{}", self.components)?;
+        if let Some(root) = &self.root {
+            writeln!(f, "Root:\t\t\t{}", root)?;
+            writeln!(
+                f,
+                "Mean distance:\t\t{}",
+                self.mean_distance
+                    .map_or_else(|| "-".to_string(), |v| format!("{:.4}", v))
+            )?;
+            writeln!(f, "Unreachable:\t\t{}", self.unreachable)?;
+        }
+        writeln!(f)?;
+        self.suggestions.iter().enumerate().try_for_each(|(i, v)| {
+            let reason = match v.reason {
+                SuggestReason::JoinComponents => "joins components".to_string(),
+                SuggestReason::ReduceDistance => format!(
+                    "mean distance {:.4} -> {:.4}",
+                    v.mean_distance_before.unwrap_or_default(),
+                    v.mean_distance_after.unwrap_or_default()
+                ),
+            };
+            writeln!(
+                f,
+                "{:>4}  {} should sign {}\t({})\n      {} -> {}",
+                i + 1,
+                v.issuer_uid,
+                v.target_uid,
+                reason,
+                v.issuer,
+                v.target
+            )
+        })
+    }
+}
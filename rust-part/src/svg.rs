@@ -0,0 +1,428 @@
+use crate::cert::get_pgp_uid_by_node_uid;
+use crate::matrix::{escape_html, format_date};
+use crate::structure::{GraphNodeUid, OpenPgpSig};
+use crate::style::Attrs;
+use petgraph::graphmap::DiGraphMap;
+use std::collections::{BTreeSet, HashMap};
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+const FONT_SIZE: f64 = 12.0;
+const CHAR_WIDTH: f64 = 7.0;
+const NODE_HEIGHT: f64 = 32.0;
+/// Size of the `image` of a node, e.g. a photo ID, drawn above its label
+const IMAGE_WIDTH: f64 = 72.0;
+const IMAGE_HEIGHT: f64 = 96.0;
+const MARGIN: f64 = 20.0;
+const ITERATIONS: usize = 300;
+const GRAVITY: f64 = 1.0;
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Point {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+pub(crate) struct Node<'a> {
+    pub(crate) uid: GraphNodeUid<'a>,
+    pub(crate) label: String,
+    pub(crate) attrs: Attrs,
+    pub(crate) center: Point,
+    pub(crate) rx: f64,
+    pub(crate) ry: f64,
+}
+
+/// Nodes placed on a canvas of the given size, in the order of the graph
+pub(crate) struct Layout<'a> {
+    pub(crate) nodes: Vec<Node<'a>>,
+    pub(crate) index: HashMap<GraphNodeUid<'a>, usize>,
+    pub(crate) width: f64,
+    pub(crate) height: f64,
+}
+
+impl Node<'_> {
+    fn is_box(&self) -> bool {
+        self.attrs.get("shape") == Some("box")
+    }
+
+    /// The point where the ray from the center in direction (dx, dy) leaves the shape
+    fn boundary(&self, dx: f64, dy: f64) -> Point {
+        let length = (dx * dx + dy * dy).sqrt().max(f64::EPSILON);
+        let (ux, uy) = (dx / length, dy / length);
+        let t = if self.is_box() {
+            (self.rx / ux.abs().max(f64::EPSILON)).min(self.ry / uy.abs().max(f64::EPSILON))
+        } else {
+            1.0 / ((ux / self.rx).powi(2) + (uy / self.ry).powi(2)).sqrt()
+        };
+        Point {
+            x: self.center.x + ux * t,
+            y: self.center.y + uy * t,
+        }
+    }
+}
+
+/// Fruchterman-Reingold force-directed layout, starting from a golden angle spiral so it's deterministic
+fn layout(sizes: &[(f64, f64)], edges: &[(usize, usize)]) -> Vec<Point> {
+    let count = sizes.len();
+    if count == 0 {
+        return Default::default();
+    }
+    let mean_width = sizes.iter().map(|(rx, _)| rx * 2.0).sum::<f64>() / count as f64;
+    let k = mean_width.max(80.0) + NODE_HEIGHT;
+    let golden_angle = PI * (3.0 - 5f64.sqrt());
+    let mut positions: Vec<Point> = (0..count)
+        .map(|i| {
+            let radius = k * (i as f64 + 0.5).sqrt();
+            let angle = i as f64 * golden_angle;
+            Point {
+                x: radius * angle.cos(),
+                y: radius * angle.sin(),
+            }
+        })
+        .collect();
+
+    let initial_temperature = k * (count as f64).sqrt();
+    (0..ITERATIONS).for_each(|iteration| {
+        let temperature = initial_temperature * (1.0 - iteration as f64 / ITERATIONS as f64);
+        let mut displacements: Vec<Point> = vec![Point { x: 0.0, y: 0.0 }; count];
+
+        (0..count).for_each(|i| {
+            (i + 1..count).for_each(|j| {
+                let dx = positions[i].x - positions[j].x;
+                let dy = positions[i].y - positions[j].y;
+                let distance = (dx * dx + dy * dy).sqrt().max(0.01);
+                let force = k * k / distance;
+                displacements[i].x += dx / distance * force;
+                displacements[i].y += dy / distance * force;
+                displacements[j].x -= dx / distance * force;
+                displacements[j].y -= dy / distance * force;
+            });
+        });
+
+        edges.iter().for_each(|(i, j)| {
+            let dx = positions[*i].x - positions[*j].x;
+            let dy = positions[*i].y - positions[*j].y;
+            let distance = (dx * dx + dy * dy).sqrt().max(0.01);
+            let force = distance * distance / k;
+            displacements[*i].x -= dx / distance * force;
+            displacements[*i].y -= dy / distance * force;
+            displacements[*j].x += dx / distance * force;
+            displacements[*j].y += dy / distance * force;
+        });
+
+        // A gravity towards the center keeps disconnected components from drifting apart
+        positions
+            .iter()
+            .zip(displacements.iter_mut())
+            .for_each(|(position, displacement)| {
+                displacement.x -= position.x * GRAVITY;
+                displacement.y -= position.y * GRAVITY;
+            });
+
+        positions
+            .iter_mut()
+            .zip(displacements.iter())
+            .for_each(|(position, displacement)| {
+                let length = (displacement.x * displacement.x + displacement.y * displacement.y)
+                    .sqrt()
+                    .max(f64::EPSILON);
+                let step = length.min(temperature);
+                position.x += displacement.x / length * step;
+                position.y += displacement.y / length * step;
+            });
+    });
+    positions
+}
+
+fn stroke(attrs: &Attrs) -> String {
+    let mut stroke = format!(
+        "stroke=\"{}\" stroke-width=\"{}\"",
+        escape_html(attrs.get("color").unwrap_or("black")),
+        escape_html(attrs.get("penwidth").unwrap_or("1"))
+    );
+    if attrs
+        .get("style")
+        .is_some_and(|v| v.split(',').any(|v| v.trim() == "dashed"))
+    {
+        stroke += " stroke-dasharray=\"5,3\"";
+    }
+    stroke
+}
+
+fn marker_id(color: &str) -> String {
+    format!(
+        "arrow-{}",
+        color
+            .chars()
+            .map(|v| if v.is_ascii_alphanumeric() { v } else { '_' })
+            .collect::<String>()
+    )
+}
+
+/// Size the nodes by their labels and place them on a canvas
+pub(crate) fn layout_graph<'a, N>(
+    graph: &DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>,
+    node_attrs: N,
+) -> Layout<'a>
+where
+    N: Fn(&GraphNodeUid) -> Attrs,
+{
+    let mut nodes: Vec<Node> = graph
+        .nodes()
+        .map(|uid| {
+            let label = get_pgp_uid_by_node_uid(&uid)
+                .map(|v| v.uid.to_string())
+                .unwrap_or_else(|| uid.fingerprint.to_string());
+            let attrs = node_attrs(&uid);
+            let mut rx = label.chars().count() as f64 * CHAR_WIDTH / 2.0 + 12.0;
+            let mut ry = NODE_HEIGHT / 2.0;
+            if attrs.get("image").is_some() {
+                rx = rx.max(IMAGE_WIDTH / 2.0 + 8.0);
+                ry += IMAGE_HEIGHT / 2.0;
+            }
+            Node {
+                uid,
+                label,
+                attrs,
+                center: Point { x: 0.0, y: 0.0 },
+                rx,
+                ry,
+            }
+        })
+        .collect();
+    let index: HashMap<GraphNodeUid, usize> =
+        nodes.iter().enumerate().map(|(i, v)| (v.uid, i)).collect();
+    let edge_index: BTreeSet<(usize, usize)> = graph
+        .all_edges()
+        .filter(|(issuer, target, _)| issuer != target)
+        .map(|(issuer, target, _)| {
+            let (i, j) = (index[&issuer], index[&target]);
+            (i.min(j), i.max(j))
+        })
+        .collect();
+    let sizes: Vec<(f64, f64)> = nodes.iter().map(|v| (v.rx, v.ry)).collect();
+    let edge_index: Vec<(usize, usize)> = edge_index.into_iter().collect();
+    let positions = layout(&sizes, &edge_index);
+
+    // Loops are drawn above the node, so leave room for them
+    let min_x = nodes
+        .iter()
+        .zip(&positions)
+        .map(|(v, p)| p.x - v.rx)
+        .fold(f64::INFINITY, f64::min);
+    let min_y = nodes
+        .iter()
+        .zip(&positions)
+        .map(|(v, p)| p.y - v.ry - NODE_HEIGHT)
+        .fold(f64::INFINITY, f64::min);
+    nodes.iter_mut().zip(&positions).for_each(|(v, p)| {
+        v.center = Point {
+            x: p.x - min_x + MARGIN,
+            y: p.y - min_y + MARGIN,
+        };
+    });
+    let width = nodes.iter().map(|v| v.center.x + v.rx).fold(0.0, f64::max) + MARGIN;
+    let height = nodes.iter().map(|v| v.center.y + v.ry).fold(0.0, f64::max) + MARGIN;
+
+    Layout {
+        nodes,
+        index,
+        width,
+        height,
+    }
+}
+
+/// Lay out the graph and render it as a standalone SVG, styled with the same attributes as the DOT output
+pub(crate) fn to_svg<'a, N, E>(
+    graph: &DiGraphMap<GraphNodeUid<'a>, &OpenPgpSig>,
+    node_attrs: N,
+    edge_attrs: E,
+) -> String
+where
+    N: Fn(&GraphNodeUid) -> Attrs,
+    E: Fn(&GraphNodeUid, &GraphNodeUid, &OpenPgpSig) -> Attrs,
+{
+    let Layout {
+        nodes,
+        index,
+        width,
+        height,
+    } = layout_graph(graph, node_attrs);
+
+    let edges: Vec<(usize, usize, &OpenPgpSig, Attrs)> = graph
+        .all_edges()
+        .map(|(issuer, target, sig)| {
+            (
+                index[&issuer],
+                index[&target],
+                *sig,
+                edge_attrs(&issuer, &target, sig),
+            )
+        })
+        .collect();
+    let colors: BTreeSet<&str> = edges
+        .iter()
+        .map(|(_, _, _, attrs)| attrs.get("color").unwrap_or("black"))
+        .collect();
+
+    let mut output = String::new();
+    output += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    writeln!(
+        output,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" viewBox=\"0 0 {:.0} {:.0}\" font-family=\"sans-serif\" font-size=\"{}\">",
+        width, height, width, height, FONT_SIZE
+    )
+    .unwrap();
+    output += "<defs>\n";
+    colors.iter().for_each(|color| {
+        writeln!(
+            output,
+            "<marker id=\"{}\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"10\" markerHeight=\"10\" markerUnits=\"userSpaceOnUse\" orient=\"auto\"><path d=\"M0,0 L10,5 L0,10 z\" fill=\"{}\"/></marker>",
+            marker_id(color),
+            escape_html(color)
+        )
+        .unwrap();
+    });
+    output += "</defs>\n<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n";
+
+    edges.iter().enumerate().for_each(|(id, (i, j, sig, attrs))| {
+        let (issuer, target) = (&nodes[*i], &nodes[*j]);
+        let color = attrs.get("color").unwrap_or("black");
+        write!(output, "<g class=\"edge\" id=\"edge-{}\">", id).unwrap();
+        write!(
+            output,
+            "<title>{} -&gt; {}: {:?} {}{}</title>",
+            escape_html(&issuer.label),
+            escape_html(&target.label),
+            sig.sig_type,
+            format_date(sig.creation_time),
+            sig.trust_label()
+                .map_or_else(Default::default, |v| format!(", {}", v))
+        )
+        .unwrap();
+        if i == j {
+            let Point { x, y } = issuer.center;
+            let top = y - issuer.ry;
+            write!(
+                output,
+                "<path d=\"M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\" fill=\"none\" {} marker-end=\"url(#{})\"/>",
+                x - 8.0,
+                top,
+                x - 24.0,
+                top - NODE_HEIGHT,
+                x + 24.0,
+                top - NODE_HEIGHT,
+                x + 8.0,
+                top,
+                stroke(attrs),
+                marker_id(color)
+            )
+            .unwrap();
+        } else {
+            let dx = target.center.x - issuer.center.x;
+            let dy = target.center.y - issuer.center.y;
+            let start = issuer.boundary(dx, dy);
+            let end = target.boundary(-dx, -dy);
+            write!(
+                output,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" {} marker-end=\"url(#{})\"/>",
+                start.x,
+                start.y,
+                end.x,
+                end.y,
+                stroke(attrs),
+                marker_id(color)
+            )
+            .unwrap();
+        }
+        output += "</g>\n";
+    });
+
+    nodes.iter().enumerate().for_each(|(id, node)| {
+        let Point { x, y } = node.center;
+        let fill = if node
+            .attrs
+            .get("style")
+            .is_some_and(|v| v.split(',').any(|v| v.trim() == "filled"))
+        {
+            node.attrs
+                .get("fillcolor")
+                .or(node.attrs.get("color"))
+                .unwrap_or("lightgray")
+        } else {
+            "white"
+        };
+        write!(output, "<g class=\"node\" id=\"node-{}\">", id).unwrap();
+        write!(
+            output,
+            "<title>{}\n{}</title>",
+            escape_html(node.uid.fingerprint),
+            escape_html(&node.label)
+        )
+        .unwrap();
+        let peripheries: usize = node
+            .attrs
+            .get("peripheries")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1);
+        (0..peripheries.max(1)).rev().for_each(|periphery| {
+            let padding = periphery as f64 * 4.0;
+            let fill = if periphery == 0 { fill } else { "none" };
+            if node.is_box() {
+                write!(
+                    output,
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" {}/>",
+                    x - node.rx - padding,
+                    y - node.ry - padding,
+                    (node.rx + padding) * 2.0,
+                    (node.ry + padding) * 2.0,
+                    escape_html(fill),
+                    stroke(&node.attrs)
+                )
+                .unwrap();
+            } else {
+                write!(
+                    output,
+                    "<ellipse cx=\"{:.1}\" cy=\"{:.1}\" rx=\"{:.1}\" ry=\"{:.1}\" fill=\"{}\" {}/>",
+                    x,
+                    y,
+                    node.rx + padding,
+                    node.ry + padding,
+                    escape_html(fill),
+                    stroke(&node.attrs)
+                )
+                .unwrap();
+            }
+        });
+        // The image fills the top of the node, and the label goes below it
+        let label_y = match node.attrs.get("image") {
+            Some(image) => {
+                write!(
+                    output,
+                    "<image href=\"{}\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" preserveAspectRatio=\"xMidYMid meet\"/>",
+                    escape_html(image),
+                    x - IMAGE_WIDTH / 2.0,
+                    y - node.ry + 4.0,
+                    IMAGE_WIDTH,
+                    IMAGE_HEIGHT - 4.0
+                )
+                .unwrap();
+                y + node.ry - NODE_HEIGHT / 2.0
+            }
+            None => y,
+        };
+        write!(
+            output,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\">{}</text>",
+            x,
+            label_y,
+            escape_html(node.attrs.get("fontcolor").unwrap_or("black")),
+            escape_html(&node.label)
+        )
+        .unwrap();
+        output += "</g>\n";
+    });
+
+    output += "</svg>\n";
+    output
+}
@@ -0,0 +1,71 @@
+//! Helpers shared by the unit tests
+
+use crate::cert::openpgp_key;
+use crate::structure::OpenPgpKey;
+use sequoia_openpgp::cert::CertBuilder;
+use sequoia_openpgp::crypto::KeyPair;
+use sequoia_openpgp::packet::signature::SignatureBuilder;
+use sequoia_openpgp::policy::StandardPolicy;
+use sequoia_openpgp::types::SignatureType;
+use sequoia_openpgp::Cert;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, remove_dir_all};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub(crate) const POLICY: &StandardPolicy = &StandardPolicy::new();
+
+pub(crate) fn generate(uid: &str) -> Cert {
+    CertBuilder::new().add_userid(uid).generate().unwrap().0
+}
+
+pub(crate) fn signer(cert: &Cert) -> KeyPair {
+    cert.primary_key()
+        .key()
+        .clone()
+        .parts_into_secret()
+        .unwrap()
+        .into_keypair()
+        .unwrap()
+}
+
+/// `cert` with a certification of its first UID by `issuer`
+pub(crate) fn certify(cert: Cert, issuer: &Cert) -> Cert {
+    certify_with(
+        cert,
+        issuer,
+        SignatureBuilder::new(SignatureType::GenericCertification),
+    )
+}
+
+/// `cert` with a signature of its first UID by `issuer`, built by `builder`
+pub(crate) fn certify_with(cert: Cert, issuer: &Cert, builder: SignatureBuilder) -> Cert {
+    let uid = cert.userids().next().unwrap().userid().clone();
+    let sig = builder
+        .sign_userid_binding(&mut signer(issuer), cert.primary_key().key(), &uid)
+        .unwrap();
+    cert.insert_packets(sig).unwrap()
+}
+
+pub(crate) fn key_set(certs: &[&Cert]) -> HashMap<Arc<String>, OpenPgpKey> {
+    certs
+        .iter()
+        .map(|cert| {
+            let pgp_key = openpgp_key(&cert.with_policy(POLICY, None).unwrap());
+            (pgp_key.id.clone(), pgp_key)
+        })
+        .collect()
+}
+
+/// A path in the temporary directory, unique to this process
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pgp-sig2dot-{}-{}", name, std::process::id()))
+}
+
+/// An empty directory at [`temp_path`]
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = temp_path(name);
+    remove_dir_all(&dir).ok();
+    create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,229 @@
+"use strict";
+
+(function () {
+  const graph = JSON.parse(document.getElementById("graph-data").textContent);
+  const svg = document.querySelector("#canvas svg");
+  const details = document.getElementById("details");
+  const search = document.getElementById("search");
+  const layerInput = document.getElementById("layer");
+  const layerValue = document.getElementById("layer-value");
+  const sigTypes = document.getElementById("sig-types");
+
+  const nodeElements = graph.nodes.map((_, i) => document.getElementById("node-" + i));
+  const edgeElements = graph.edges.map((_, i) => document.getElementById("edge-" + i));
+
+  const sigTypeName = (sigType) =>
+    typeof sigType === "string" ? sigType : "Unknown";
+  const formatDate = (timestamp) =>
+    new Date(timestamp * 1000).toISOString().slice(0, 10);
+  const element = (tag, text, className) => {
+    const e = document.createElement(tag);
+    if (text !== undefined) e.textContent = text;
+    if (className) e.className = className;
+    return e;
+  };
+
+  let selected = null;
+  let hiddenSigTypes = new Set();
+  let maxLayer = null;
+
+  const layers = graph.nodes
+    .map((v) => v.layer)
+    .filter((v) => v !== null && v !== undefined);
+  if (layers.length > 0) {
+    const max = Math.max(...layers);
+    layerInput.max = max;
+    layerInput.value = max;
+    layerValue.textContent = max;
+  } else {
+    document.getElementById("layer-filter").hidden = true;
+  }
+
+  const nodeVisible = (i) => {
+    if (maxLayer === null) return true;
+    const layer = graph.nodes[i].layer;
+    return layer !== null && layer !== undefined && layer <= maxLayer;
+  };
+
+  const edgeVisible = (edge) =>
+    !hiddenSigTypes.has(sigTypeName(edge.sig.sig_type)) &&
+    nodeVisible(edge.source) &&
+    nodeVisible(edge.target);
+
+  function update() {
+    const query = search.value.trim().toLowerCase().replace(/\s+/g, "");
+    graph.nodes.forEach((node, i) => {
+      const e = nodeElements[i];
+      if (!e) return;
+      const uid = node.user_id || {};
+      const haystack = [node.label, uid.name, uid.email, node.fingerprint]
+        .filter((v) => v)
+        .join("\n")
+        .toLowerCase()
+        .replace(/ /g, "");
+      e.style.display = nodeVisible(i) ? "" : "none";
+      e.classList.toggle("dimmed", query !== "" && !haystack.includes(query));
+      e.classList.toggle("selected", selected === i);
+    });
+    graph.edges.forEach((edge, i) => {
+      const e = edgeElements[i];
+      if (!e) return;
+      e.style.display = edgeVisible(edge) ? "" : "none";
+      e.classList.toggle(
+        "dimmed",
+        selected !== null && edge.source !== selected && edge.target !== selected,
+      );
+    });
+  }
+
+  function sigList(title, edges, other) {
+    const section = element("section");
+    section.appendChild(element("h3", title + " (" + edges.length + ")"));
+    const list = element("ul");
+    edges.forEach((edge) => {
+      const i = edge[other];
+      const item = element("li");
+      const link = element("a", graph.nodes[i].label);
+      link.href = "#";
+      link.addEventListener("click", (event) => {
+        event.preventDefault();
+        select(i);
+      });
+      item.appendChild(link);
+      const sig = edge.sig;
+      let text = " " + sigTypeName(sig.sig_type) + " " + formatDate(sig.creation_time);
+      if (sig.trust_level > 0) {
+        text += " trust " + sig.trust_level + "/" + sig.trust_value;
+      }
+      item.appendChild(element("span", text, "sig"));
+      list.appendChild(item);
+    });
+    section.appendChild(list);
+    return section;
+  }
+
+  function keyList(key) {
+    const section = element("section");
+    const keys = [key.primary_key].concat(key.subkeys);
+    section.appendChild(element("h3", "Keys (" + keys.length + ")"));
+    if (key.invalid_reason) {
+      section.appendChild(element("p", "Invalid: " + key.invalid_reason, "warning"));
+    }
+    if (!key.capabilities.some((v) => v.startsWith("encrypt"))) {
+      section.appendChild(element("p", "This key can't encrypt.", "warning"));
+    }
+    const list = element("ul");
+    keys.forEach((v) => {
+      let text = v.algorithm + (v.bits ? " " + v.bits : "");
+      text += ": " + (v.capabilities.join(", ") || "no capabilities");
+      let state = "created " + formatDate(v.creation_time);
+      if (v.expiration_time) state += ", expires " + formatDate(v.expiration_time);
+      if (v.is_expired) state += ", expired";
+      if (v.is_revoked) state += ", revoked";
+      const item = element("li", text, v.is_expired || v.is_revoked ? "unusable" : undefined);
+      item.appendChild(element("span", " " + state, "sig"));
+      list.appendChild(item);
+    });
+    section.appendChild(list);
+    return section;
+  }
+
+  function select(i) {
+    selected = i;
+    details.replaceChildren();
+    if (i === null) {
+      details.appendChild(element("p", "Click a node to see its details.", "hint"));
+      update();
+      return;
+    }
+    const node = graph.nodes[i];
+    details.appendChild(element("h2", node.label));
+    const table = element("table");
+    const fields = Object.assign(
+      { fingerprint: node.fingerprint },
+      node.user_id || { unknown: true },
+    );
+    if (node.layer !== null && node.layer !== undefined) fields.layer = node.layer;
+    Object.entries(fields).forEach(([key, value]) => {
+      const row = element("tr");
+      row.appendChild(element("th", key));
+      row.appendChild(element("td", String(value)));
+      table.appendChild(row);
+    });
+    details.appendChild(table);
+    if (node.key) details.appendChild(keyList(node.key));
+    const visible = graph.edges.filter(edgeVisible);
+    details.appendChild(sigList("Incoming", visible.filter((v) => v.target === i), "source"));
+    details.appendChild(sigList("Outgoing", visible.filter((v) => v.source === i), "target"));
+    update();
+  }
+
+  nodeElements.forEach((e, i) => {
+    if (!e) return;
+    e.addEventListener("click", (event) => {
+      event.stopPropagation();
+      select(i);
+    });
+  });
+  let dragged = false;
+  svg.addEventListener("click", () => {
+    if (!dragged) select(null);
+  });
+
+  [...new Set(graph.edges.map((v) => sigTypeName(v.sig.sig_type)))]
+    .sort()
+    .forEach((sigType) => {
+      const label = element("label");
+      const checkbox = element("input");
+      checkbox.type = "checkbox";
+      checkbox.checked = true;
+      checkbox.addEventListener("change", () => {
+        if (checkbox.checked) hiddenSigTypes.delete(sigType);
+        else hiddenSigTypes.add(sigType);
+        select(selected);
+      });
+      label.appendChild(checkbox);
+      label.appendChild(document.createTextNode(" " + sigType));
+      sigTypes.appendChild(label);
+    });
+
+  search.addEventListener("input", update);
+  layerInput.addEventListener("input", () => {
+    const value = Number(layerInput.value);
+    layerValue.textContent = value;
+    maxLayer = value >= Number(layerInput.max) ? null : value;
+    select(selected);
+  });
+
+  // Pan by dragging and zoom with the mouse wheel
+  const viewBox = svg.viewBox.baseVal;
+  let drag = null;
+  svg.addEventListener("mousedown", (event) => {
+    drag = { x: event.clientX, y: event.clientY };
+    dragged = false;
+  });
+  window.addEventListener("mouseup", () => {
+    drag = null;
+  });
+  window.addEventListener("mousemove", (event) => {
+    if (!drag) return;
+    const scale = viewBox.width / svg.clientWidth;
+    viewBox.x -= (event.clientX - drag.x) * scale;
+    viewBox.y -= (event.clientY - drag.y) * scale;
+    drag = { x: event.clientX, y: event.clientY };
+    dragged = true;
+  });
+  svg.addEventListener("wheel", (event) => {
+    event.preventDefault();
+    const factor = event.deltaY > 0 ? 1.1 : 1 / 1.1;
+    const rect = svg.getBoundingClientRect();
+    const x = viewBox.x + ((event.clientX - rect.left) / rect.width) * viewBox.width;
+    const y = viewBox.y + ((event.clientY - rect.top) / rect.height) * viewBox.height;
+    viewBox.x = x - (x - viewBox.x) * factor;
+    viewBox.y = y - (y - viewBox.y) * factor;
+    viewBox.width *= factor;
+    viewBox.height *= factor;
+  });
+
+  select(null);
+})();